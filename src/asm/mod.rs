@@ -0,0 +1,597 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use crate::chip8::Instruction;
+
+mod token;
+
+use token::{tokenize, Kind, Token};
+
+pub const ROM_ADDRESS: u16 = 0x200;
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+pub const SYNTAX: &str = "\
+one statement per line, ; starts a comment
+  name:                  label, the address of the next statement
+  name = expression      constant
+  byte 1, 0x2F, \"text\"   bytes
+  word 0x1234, label     big endian 16-bit values
+  include \"file.asm\"     assemble another file here, relative to this one
+  CLS, LD V0, 0x05, ...  instructions as shown by the disassembler
+numbers are decimal, 0x hexadecimal or 0b binary, expressions are numbers and
+symbols joined with + and -";
+
+// AsmError is an error in the source, line and column start at 1
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub path: Option<PathBuf>, // None for the source given to assemble()
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(path) = &self.path {
+            write!(f, "{}:", path.display())?;
+        }
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+// Failure is an error whose line is not known yet: column and message
+type Failure = (usize, String);
+
+#[derive(Debug, Clone)]
+struct Location {
+    path: Option<Rc<Path>>,
+    line: usize,
+}
+
+impl Location {
+    fn error(&self, (column, message): Failure) -> AsmError {
+        AsmError {
+            path: self.path.as_deref().map(Path::to_path_buf),
+            line: self.line,
+            column,
+            message,
+        }
+    }
+}
+
+// Expr is a sum of numbers and symbols, like label + 2
+#[derive(Debug, Clone)]
+struct Expr {
+    tokens: Vec<Token>,
+    column: usize,
+}
+
+// Operand is one comma separated part of an instruction
+#[derive(Debug, Clone)]
+enum Operand {
+    Register(u8), // V0 to VF
+    Index, // I
+    IndexMemory, // [I]
+    Delay, // DT
+    Sound, // ST
+    Key, // K
+    Font, // F
+    BigFont, // HF
+    Bcd, // B
+    Flags, // R
+    Long(Expr), // LONG nnnn
+    Value(Expr),
+}
+
+impl Operand {
+    fn parse(tokens: Vec<Token>, column: usize) -> Result<Operand, Failure> {
+        if tokens.is_empty() {
+            return Err((column, "missing operand".to_string()));
+        }
+        if tokens.len() == 3 && tokens[0].is('[') && tokens[2].is(']') && tokens[1].text.eq_ignore_ascii_case("I") {
+            return Ok(Operand::IndexMemory);
+        }
+        let first = &tokens[0];
+        if first.kind == Kind::Word && first.text.eq_ignore_ascii_case("LONG") {
+            let column = tokens.get(1).map_or(first.column + 4, |token| token.column);
+            return Ok(Operand::Long(Expr { tokens: tokens[1..].to_vec(), column }));
+        }
+        if tokens.len() == 1 && first.kind == Kind::Word {
+            if let Some(register) = register(&first.text) {
+                return Ok(Operand::Register(register));
+            }
+            let keyword = match first.text.to_ascii_uppercase().as_str() {
+                "I" => Some(Operand::Index),
+                "DT" => Some(Operand::Delay),
+                "ST" => Some(Operand::Sound),
+                "K" => Some(Operand::Key),
+                "F" => Some(Operand::Font),
+                "HF" => Some(Operand::BigFont),
+                "B" => Some(Operand::Bcd),
+                "R" => Some(Operand::Flags),
+                _ => None,
+            };
+            if let Some(keyword) = keyword {
+                return Ok(keyword);
+            }
+        }
+        Ok(Operand::Value(Expr { column: first.column, tokens }))
+    }
+}
+
+// register parses V0 to VF
+fn register(word: &str) -> Option<u8> {
+    let digit = word.strip_prefix(['V', 'v'])?;
+    if digit.len() != 1 {
+        return None;
+    }
+    u8::from_str_radix(digit, 16).ok()
+}
+
+fn parse_number(word: &str) -> Option<i64> {
+    let lower = word.to_ascii_lowercase();
+    if let Some(hex) = lower.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()
+    } else {
+        lower.parse().ok()
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Data {
+    Value(Expr),
+    Text(String),
+}
+
+#[derive(Debug, Clone)]
+enum Body {
+    Instruction { mnemonic: Token, operands: Vec<Operand> },
+    Bytes(Vec<Data>),
+    Words(Vec<Expr>),
+}
+
+#[derive(Debug, Clone)]
+struct Statement {
+    location: Location,
+    address: u16,
+    body: Body,
+}
+
+#[derive(Debug, Clone)]
+enum Symbol {
+    Label(u16),
+    Constant(Expr),
+}
+
+// split_operands splits tokens at top level commas
+fn split_operands(tokens: &[Token]) -> Vec<(Vec<Token>, usize)> {
+    if tokens.is_empty() {
+        return Vec::new();
+    }
+    let mut operands = Vec::new();
+    let mut current = Vec::new();
+    let mut start = tokens[0].column;
+    for token in tokens {
+        if token.is(',') {
+            operands.push((std::mem::take(&mut current), start));
+            start = token.column + 1;
+        } else {
+            current.push(token.clone());
+        }
+    }
+    operands.push((current, start));
+    operands
+}
+
+// Values checks and computes the operands of one instruction
+struct Values<'a> {
+    column: usize, // of the mnemonic, for operands of the wrong kind
+    eval: &'a mut dyn FnMut(&Expr) -> Result<i64, Failure>,
+}
+
+impl Values<'_> {
+    fn value(&mut self, operand: &Operand, min: i64, max: i64, what: &str) -> Result<i64, Failure> {
+        let Operand::Value(expr) = operand else {
+            return Err((self.column, format!("expected {}", what)));
+        };
+        self.check(expr, min, max, what)
+    }
+
+    fn check(&mut self, expr: &Expr, min: i64, max: i64, what: &str) -> Result<i64, Failure> {
+        let value = (self.eval)(expr)?;
+        if value < min || value > max {
+            return Err((expr.column, format!("{} is out of range for {}", value, what)));
+        }
+        Ok(value)
+    }
+
+    // byte also takes negative numbers, -1 is 0xFF
+    fn byte(&mut self, operand: &Operand) -> Result<u8, Failure> {
+        self.value(operand, -128, 255, "a byte").map(|value| value as u8)
+    }
+
+    fn address(&mut self, operand: &Operand) -> Result<u16, Failure> {
+        self.value(operand, 0, 0xFFF, "an address").map(|value| value as u16)
+    }
+
+    fn nibble(&mut self, operand: &Operand) -> Result<u8, Failure> {
+        self.value(operand, 0, 15, "a number from 0 to 15").map(|value| value as u8)
+    }
+}
+
+// build turns a mnemonic and its operands into an instruction, eval gives the value of expressions
+fn build(mnemonic: &Token, operands: &[Operand], eval: &mut dyn FnMut(&Expr) -> Result<i64, Failure>)
+         -> Result<Instruction, Failure> {
+    let column = mnemonic.column;
+    let mut values = Values { column, eval };
+
+    use Operand::*;
+    let name = mnemonic.text.to_ascii_uppercase();
+    let instruction = match (name.as_str(), operands) {
+        ("SYS", [a]) => Instruction::Sys(values.address(a)?),
+        ("CLS", []) => Instruction::Cls,
+        ("RET", []) => Instruction::Ret,
+        ("SCD", [n]) => Instruction::ScrollDown(values.nibble(n)?),
+        ("SCU", [n]) => Instruction::ScrollUp(values.nibble(n)?),
+        ("SCR", []) => Instruction::ScrollRight,
+        ("SCL", []) => Instruction::ScrollLeft,
+        ("EXIT", []) => Instruction::Exit,
+        ("LOW", []) => Instruction::Low,
+        ("HIGH", []) => Instruction::High,
+        ("JP", [Register(0), a]) => Instruction::JumpOffset(values.address(a)?),
+        ("JP", [a]) => Instruction::Jump(values.address(a)?),
+        ("CALL", [a]) => Instruction::Call(values.address(a)?),
+        ("SE", [Register(x), Register(y)]) => Instruction::SkipEqual(*x, *y),
+        ("SE", [Register(x), b]) => Instruction::SkipEqualByte(*x, values.byte(b)?),
+        ("SNE", [Register(x), Register(y)]) => Instruction::SkipNotEqual(*x, *y),
+        ("SNE", [Register(x), b]) => Instruction::SkipNotEqualByte(*x, values.byte(b)?),
+        ("SAVE", [Register(x), Register(y)]) => Instruction::SaveRange(*x, *y),
+        ("LOAD", [Register(x), Register(y)]) => Instruction::LoadRange(*x, *y),
+        ("LD", [Index, Long(expr)]) => Instruction::LongIndex(values.check(expr, 0, 0xFFFF, "a long address")? as u16),
+        ("LD", [Index, a]) => Instruction::LoadIndex(values.address(a)?),
+        ("LD", [Register(x), Register(y)]) => Instruction::Load(*x, *y),
+        ("LD", [Register(x), Delay]) => Instruction::GetDelay(*x),
+        ("LD", [Register(x), Key]) => Instruction::WaitKey(*x),
+        ("LD", [Register(x), IndexMemory]) => Instruction::Restore(*x),
+        ("LD", [Register(x), Flags]) => Instruction::LoadFlags(*x),
+        ("LD", [Register(x), b]) => Instruction::LoadByte(*x, values.byte(b)?),
+        ("LD", [Delay, Register(x)]) => Instruction::SetDelay(*x),
+        ("LD", [Sound, Register(x)]) => Instruction::SetSound(*x),
+        ("LD", [Font, Register(x)]) => Instruction::Font(*x),
+        ("LD", [BigFont, Register(x)]) => Instruction::BigFont(*x),
+        ("LD", [Bcd, Register(x)]) => Instruction::Bcd(*x),
+        ("LD", [IndexMemory, Register(x)]) => Instruction::Store(*x),
+        ("LD", [Flags, Register(x)]) => Instruction::SaveFlags(*x),
+        ("ADD", [Index, Register(x)]) => Instruction::AddIndex(*x),
+        ("ADD", [Register(x), Register(y)]) => Instruction::Add(*x, *y),
+        ("ADD", [Register(x), b]) => Instruction::AddByte(*x, values.byte(b)?),
+        ("OR", [Register(x), Register(y)]) => Instruction::Or(*x, *y),
+        ("AND", [Register(x), Register(y)]) => Instruction::And(*x, *y),
+        ("XOR", [Register(x), Register(y)]) => Instruction::Xor(*x, *y),
+        ("SUB", [Register(x), Register(y)]) => Instruction::Sub(*x, *y),
+        ("SUBN", [Register(x), Register(y)]) => Instruction::SubN(*x, *y),
+        ("SHR", [Register(x)]) => Instruction::ShiftRight(*x, *x),
+        ("SHR", [Register(x), Register(y)]) => Instruction::ShiftRight(*x, *y),
+        ("SHL", [Register(x)]) => Instruction::ShiftLeft(*x, *x),
+        ("SHL", [Register(x), Register(y)]) => Instruction::ShiftLeft(*x, *y),
+        ("RND", [Register(x), b]) => Instruction::Random(*x, values.byte(b)?),
+        ("DRW", [Register(x), Register(y), n]) => Instruction::Draw(*x, *y, values.nibble(n)?),
+        ("SKP", [Register(x)]) => Instruction::SkipKey(*x),
+        ("SKNP", [Register(x)]) => Instruction::SkipNotKey(*x),
+        ("PLANE", [n]) => Instruction::Plane(values.nibble(n)?),
+        ("AUDIO", []) => Instruction::Audio,
+        ("PITCH", [Register(x)]) => Instruction::Pitch(*x),
+        ("SYS" | "CLS" | "RET" | "SCD" | "SCU" | "SCR" | "SCL" | "EXIT" | "LOW" | "HIGH" | "JP" | "CALL"
+         | "SE" | "SNE" | "SAVE" | "LOAD" | "LD" | "ADD" | "OR" | "AND" | "XOR" | "SUB" | "SUBN" | "SHR"
+         | "SHL" | "RND" | "DRW" | "SKP" | "SKNP" | "PLANE" | "AUDIO" | "PITCH", _) =>
+            return Err((column, format!("invalid operands for {}", name))),
+        _ => return Err((column, format!("unknown instruction {}", mnemonic.text))),
+    };
+    Ok(instruction)
+}
+
+// Assembler collects statements and symbols in the first pass and encodes them in the second
+#[derive(Default)]
+struct Assembler {
+    statements: Vec<Statement>,
+    symbols: HashMap<String, (Symbol, Location, usize)>, // with where it was defined
+    constants: Vec<String>, // in the order of definition
+    address: u32,
+}
+
+impl Assembler {
+    // read runs the first pass over one source file
+    fn read(&mut self, source: &str, path: Option<Rc<Path>>, depth: usize) -> Result<(), AsmError> {
+        for (index, line) in source.lines().enumerate() {
+            let location = Location { path: path.clone(), line: index + 1 };
+            let mut tokens = tokenize(line).map_err(|failure| location.error(failure))?;
+
+            // a label can be followed by a statement on the same line
+            if tokens.len() >= 2 && tokens[0].kind == Kind::Word && tokens[1].is(':') {
+                let name = tokens[0].clone();
+                self.define(&name, Symbol::Label(self.address as u16), &location)?;
+                tokens.drain(..2);
+            }
+            if tokens.is_empty() {
+                continue;
+            }
+            if tokens.len() >= 2 && tokens[0].kind == Kind::Word && tokens[1].is('=') {
+                let name = tokens[0].clone();
+                let column = tokens.get(2).map_or(tokens[1].column + 1, |token| token.column);
+                let expr = Expr { tokens: tokens[2..].to_vec(), column };
+                self.define(&name, Symbol::Constant(expr), &location)?;
+                self.constants.push(name.text);
+                continue;
+            }
+
+            let first = &tokens[0];
+            if first.kind != Kind::Word {
+                return Err(location.error((first.column, format!("unexpected {}", first.text))));
+            }
+            let operands = split_operands(&tokens[1..]);
+            let (body, size) = match first.text.to_ascii_lowercase().as_str() {
+                "include" => {
+                    let [(file, column)] = operands.as_slice() else {
+                        return Err(location.error((first.column, "include needs one file name".to_string())));
+                    };
+                    let [Token { kind: Kind::Text, text, .. }] = file.as_slice() else {
+                        return Err(location.error((*column, "the file name must be quoted".to_string())));
+                    };
+                    self.include(text, &location, *column, depth)?;
+                    continue;
+                },
+                "byte" => {
+                    let mut data = Vec::new();
+                    let mut size = 0;
+                    for (tokens, column) in operands {
+                        match tokens.as_slice() {
+                            [Token { kind: Kind::Text, text, .. }] => {
+                                size += text.len();
+                                data.push(Data::Text(text.clone()));
+                            },
+                            [] => return Err(location.error((column, "missing value".to_string()))),
+                            _ => {
+                                size += 1;
+                                data.push(Data::Value(Expr { tokens, column }));
+                            },
+                        }
+                    }
+                    (Body::Bytes(data), size)
+                },
+                "word" => {
+                    let mut words = Vec::new();
+                    for (tokens, column) in operands {
+                        if tokens.is_empty() {
+                            return Err(location.error((column, "missing value".to_string())));
+                        }
+                        words.push(Expr { tokens, column });
+                    }
+                    let size = words.len() * 2;
+                    (Body::Words(words), size)
+                },
+                _ => {
+                    let operands = operands.into_iter()
+                        .map(|(tokens, column)| Operand::parse(tokens, column))
+                        .collect::<Result<Vec<Operand>, Failure>>()
+                        .map_err(|failure| location.error(failure))?;
+                    // the values are not known yet, but the size of the instruction is
+                    let instruction = build(first, &operands, &mut |_| Ok(0))
+                        .map_err(|failure| location.error(failure))?;
+                    (Body::Instruction { mnemonic: first.clone(), operands }, instruction.size())
+                },
+            };
+            self.statements.push(Statement { location: location.clone(), address: self.address as u16, body });
+            self.address += size as u32;
+            if self.address > 0x10000 {
+                return Err(location.error((first.column, "program does not fit in 64K".to_string())));
+            }
+        }
+        Ok(())
+    }
+
+    fn include(&mut self, file: &str, location: &Location, column: usize, depth: usize) -> Result<(), AsmError> {
+        if depth >= MAX_INCLUDE_DEPTH {
+            return Err(location.error((column, "includes are nested too deep".to_string())));
+        }
+        let path = match location.path.as_deref().and_then(Path::parent) {
+            Some(directory) => directory.join(file),
+            None => PathBuf::from(file),
+        };
+        let source = fs::read_to_string(&path)
+            .map_err(|err| location.error((column, format!("cannot read {}: {}", path.display(), err))))?;
+        self.read(&source, Some(Rc::from(path.as_path())), depth + 1)
+    }
+
+    fn define(&mut self, name: &Token, symbol: Symbol, location: &Location) -> Result<(), AsmError> {
+        let valid = name.text.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_' || c == '.')
+            && register(&name.text).is_none();
+        if !valid {
+            return Err(location.error((name.column, format!("{} can't be used as a name", name.text))));
+        }
+        if let Some((_, defined, _)) = self.symbols.get(&name.text) {
+            return Err(location.error((name.column, format!("{} is already defined on line {}", name.text, defined.line))));
+        }
+        self.symbols.insert(name.text.clone(), (symbol, location.clone(), name.column));
+        Ok(())
+    }
+
+    // eval computes an expression, active holds the constants being computed to catch cycles
+    fn eval(&self, expr: &Expr, active: &mut Vec<String>) -> Result<i64, Failure> {
+        if expr.tokens.is_empty() {
+            return Err((expr.column, "missing value".to_string()));
+        }
+        let mut total: i64 = 0;
+        let mut sign = 1;
+        let mut expect_term = true;
+        for token in &expr.tokens {
+            if expect_term {
+                if token.is('-') {
+                    sign = -sign;
+                    continue;
+                }
+                if token.kind != Kind::Word {
+                    return Err((token.column, format!("expected a number or a name, found {}", token.text)));
+                }
+                total = self.term(token, active)?.checked_mul(sign)
+                    .and_then(|value| total.checked_add(value))
+                    .ok_or_else(|| (token.column, "value is too big".to_string()))?;
+                expect_term = false;
+            } else if token.is('+') || token.is('-') {
+                sign = if token.is('+') { 1 } else { -1 };
+                expect_term = true;
+            } else {
+                return Err((token.column, format!("expected + or -, found {}", token.text)));
+            }
+        }
+        if expect_term {
+            let last = expr.tokens.last().expect("tokens are not empty");
+            return Err((last.column + 1, "missing value".to_string()));
+        }
+        Ok(total)
+    }
+
+    fn term(&self, token: &Token, active: &mut Vec<String>) -> Result<i64, Failure> {
+        if token.text.starts_with(|c: char| c.is_ascii_digit()) {
+            return parse_number(&token.text).ok_or_else(|| (token.column, format!("invalid number {}", token.text)));
+        }
+        match self.symbols.get(&token.text) {
+            Some((Symbol::Label(address), ..)) => Ok(*address as i64),
+            Some((Symbol::Constant(expr), ..)) => {
+                if active.contains(&token.text) {
+                    return Err((token.column, format!("{} depends on itself", token.text)));
+                }
+                active.push(token.text.clone());
+                let value = self.eval(expr, active)
+                    .map_err(|(_, message)| (token.column, format!("in {}: {}", token.text, message)));
+                active.pop();
+                value
+            },
+            None => Err((token.column, format!("undefined name {}", token.text))),
+        }
+    }
+
+    // encode runs the second pass, when all labels are known
+    fn encode(&self) -> Result<Vec<u8>, AsmError> {
+        for name in &self.constants {
+            let (Symbol::Constant(expr), location, _) = &self.symbols[name] else { continue };
+            self.eval(expr, &mut vec![name.clone()]).map_err(|failure| location.error(failure))?;
+        }
+
+        let mut rom = Vec::new();
+        for statement in &self.statements {
+            let location = &statement.location;
+            let mut eval = |expr: &Expr| self.eval(expr, &mut Vec::new());
+            debug_assert_eq!(rom.len() + ROM_ADDRESS as usize, statement.address as usize);
+            match &statement.body {
+                Body::Instruction { mnemonic, operands } => {
+                    let instruction = build(mnemonic, operands, &mut eval).map_err(|failure| location.error(failure))?;
+                    rom.extend(instruction.bytes());
+                },
+                Body::Bytes(data) => for item in data {
+                    match item {
+                        Data::Text(text) => rom.extend(text.bytes()),
+                        Data::Value(expr) => {
+                            let value = eval(expr).map_err(|failure| location.error(failure))?;
+                            if !(-128..=255).contains(&value) {
+                                let failure = (expr.column, format!("{} is out of range for a byte", value));
+                                return Err(location.error(failure));
+                            }
+                            rom.push(value as u8);
+                        },
+                    }
+                },
+                Body::Words(words) => for expr in words {
+                    let value = eval(expr).map_err(|failure| location.error(failure))?;
+                    if !(-0x8000..=0xFFFF).contains(&value) {
+                        let failure = (expr.column, format!("{} is out of range for a word", value));
+                        return Err(location.error(failure));
+                    }
+                    rom.extend((value as u16).to_be_bytes());
+                },
+            }
+        }
+        Ok(rom)
+    }
+}
+
+fn assemble_source(source: &str, path: Option<&Path>) -> Result<Vec<u8>, AsmError> {
+    let mut assembler = Assembler { address: ROM_ADDRESS as u32, ..Assembler::default() };
+    assembler.read(source, path.map(Rc::from), 0)?;
+    assembler.encode()
+}
+
+// assemble turns source into a ROM for Chip8::load_rom, the program starts at 0x200.
+// included files are looked up relative to the current directory
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    assemble_source(source, None)
+}
+
+// assemble_file assembles a file, included files are looked up relative to it
+pub fn assemble_file(path: &Path) -> Result<Vec<u8>, AsmError> {
+    let source = fs::read_to_string(path).map_err(|err| AsmError {
+        path: Some(path.to_path_buf()),
+        line: 0,
+        column: 0,
+        message: format!("cannot read file: {}", err),
+    })?;
+    assemble_source(&source, Some(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disasm::disassemble;
+
+    #[test]
+    fn assembles_labels_constants_and_data() {
+        let source = "\
+SPEED = 3
+start:  LD V0, SPEED + 1   ; comment
+        LD I, sprite
+loop:   DRW V0, V1, 2
+        ADD V0, -1
+        SE V0, 0
+        JP loop
+        LD I, LONG sprite
+        SHR V3
+        JP start
+sprite: byte 0b11110000, 0x90, \"A\"
+        word loop - 2
+";
+        let rom = assemble(source).unwrap();
+        assert_eq!(rom, [
+            0x60, 0x04, 0xA2, 0x14, 0xD0, 0x12, 0x70, 0xFF, 0x30, 0x00, 0x12, 0x04,
+            0xF0, 0x00, 0x02, 0x14, 0x83, 0x36, 0x12, 0x00, 0xF0, 0x90, b'A', 0x02, 0x02,
+        ]);
+    }
+
+    #[test]
+    fn reports_line_and_column() {
+        let error = assemble("CLS\n  LD V0, nowhere").unwrap_err();
+        assert_eq!((error.line, error.column), (2, 10));
+        let error = assemble("  DRW V0, V1, 16").unwrap_err();
+        assert_eq!((error.line, error.column, error.message.as_str()),
+                   (1, 15, "16 is out of range for a number from 0 to 15"));
+        assert!(assemble("a = b\nb = a\nCLS").is_err());
+        assert_eq!(assemble("x: CLS\nx: RET").unwrap_err().column, 1);
+        assert_eq!(assemble("  MOV V0, V1").unwrap_err().message, "unknown instruction MOV");
+        let error = assemble("  LD V0, 0x7FFFFFFFFFFFFFFF + 1").unwrap_err();
+        assert_eq!((error.line, error.column, error.message.as_str()), (1, 31, "value is too big"));
+        assert!(assemble("a = -0x7FFFFFFFFFFFFFFF - 1
+  LD V0, -a").is_err());
+    }
+
+    #[test]
+    fn disassembly_assembles_back() {
+        for rom in [&include_bytes!("../../example_roms/IBM Logo.ch8")[..],
+                    &include_bytes!("../../example_roms/Tetris [Fran Dachille, 1991].ch8")[..]] {
+            assert_eq!(assemble(&disassemble(rom)).unwrap(), rom);
+        }
+    }
+}
@@ -0,0 +1,64 @@
+// Kind is what a token is made of
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Kind {
+    Word, // mnemonic, register, number or symbol
+    Text, // "quoted string", the text is without the quotes
+    Punct(char), // one of , : [ ] + - =
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Token {
+    pub kind: Kind,
+    pub text: String,
+    pub column: usize, // 1-based, in characters
+}
+
+impl Token {
+    pub fn is(&self, c: char) -> bool {
+        self.kind == Kind::Punct(c)
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.'
+}
+
+// tokenize splits a line into tokens, everything after ; is a comment.
+// the error is the column and a message
+pub(crate) fn tokenize(line: &str) -> Result<Vec<Token>, (usize, String)> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i + 1;
+        if c == ';' {
+            break;
+        } else if c.is_whitespace() {
+            i += 1;
+        } else if is_word_char(c) {
+            let start = i;
+            while i < chars.len() && is_word_char(chars[i]) {
+                i += 1;
+            }
+            tokens.push(Token { kind: Kind::Word, text: chars[start..i].iter().collect(), column });
+        } else if c == '"' {
+            let start = i + 1;
+            i = start;
+            while i < chars.len() && chars[i] != '"' {
+                i += 1;
+            }
+            if i == chars.len() {
+                return Err((column, "string is not closed".to_string()));
+            }
+            tokens.push(Token { kind: Kind::Text, text: chars[start..i].iter().collect(), column });
+            i += 1;
+        } else if ",:[]+-=".contains(c) {
+            tokens.push(Token { kind: Kind::Punct(c), text: c.to_string(), column });
+            i += 1;
+        } else {
+            return Err((column, format!("unexpected character {:?}", c)));
+        }
+    }
+    Ok(tokens)
+}
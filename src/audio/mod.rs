@@ -0,0 +1,194 @@
+use std::io;
+use crate::chip8::Chip8;
+
+mod sink;
+
+pub use sink::{read_wav, DeviceSink, NullSink, WavSink};
+
+// Waveform is the shape of the beep
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Waveform {
+    #[default]
+    Square,
+    Triangle,
+    Sawtooth,
+    Sine,
+}
+
+impl Waveform {
+    pub fn from_name(name: &str) -> Option<Waveform> {
+        match name.to_ascii_lowercase().as_str() {
+            "square" => Some(Waveform::Square),
+            "triangle" => Some(Waveform::Triangle),
+            "sawtooth" | "saw" => Some(Waveform::Sawtooth),
+            "sine" => Some(Waveform::Sine),
+            _ => None,
+        }
+    }
+
+    // sample returns the wave at phase (0 to 1) between -1 and 1
+    fn sample(self, phase: f64) -> f64 {
+        match self {
+            Waveform::Square => if phase < 0.5 { 1.0 } else { -1.0 },
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Sawtooth => 2.0 * phase - 1.0,
+            Waveform::Sine => (phase * std::f64::consts::TAU).sin(),
+        }
+    }
+}
+
+// AudioConfig is how the beep sounds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AudioConfig {
+    pub sample_rate: u32,
+    pub frequency: f64, // of the beep, the XO-CHIP pattern has its own pitch
+    pub volume: f64, // 0 to 1
+    pub waveform: Waveform,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        AudioConfig { sample_rate: 44100, frequency: 440.0, volume: 0.25, waveform: Waveform::Square }
+    }
+}
+
+// AudioSink receives the samples, 16-bit signed mono at the configured sample rate
+pub trait AudioSink: Send {
+    fn write(&mut self, samples: &[i16]) -> io::Result<()>;
+
+    // finish is called once after the last samples
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Beeper turns the sound timer into samples, one 60Hz frame at a time: frame is called just
+// before the timers tick, the beep sounds for the whole frame if the sound timer is above 0.
+// Programs that loaded an XO-CHIP pattern hear it instead of the beep. Write errors stop
+// the sound, the first one is kept and returned by finish()
+pub struct Beeper {
+    config: AudioConfig,
+    sink: Box<dyn AudioSink>,
+    phase: f64, // position in the period of the beep, 0 to 1
+    pattern_position: f64, // bit of the XO-CHIP pattern, 0 to 128
+    frames: u64,
+    samples: u64,
+    buffer: Vec<i16>,
+    error: Option<io::Error>,
+}
+
+impl Beeper {
+    pub fn new(config: AudioConfig, sink: Box<dyn AudioSink>) -> Beeper {
+        Beeper {
+            config,
+            sink,
+            phase: 0.0,
+            pattern_position: 0.0,
+            frames: 0,
+            samples: 0,
+            buffer: Vec::new(),
+            error: None,
+        }
+    }
+
+    pub fn config(&self) -> AudioConfig {
+        self.config
+    }
+
+    // samples returns how many samples were written
+    pub fn samples(&self) -> u64 {
+        self.samples
+    }
+
+    // frame writes the samples of the next frame, silence if audible is false. Frames are
+    // sample_rate / 60 samples long, rounded so that frame n always starts at sample
+    // n * sample_rate / 60
+    pub fn frame(&mut self, chip8: &Chip8, audible: bool) {
+        let end = (self.frames + 1) * self.config.sample_rate as u64 / 60;
+        let len = (end - self.samples) as usize;
+        self.frames += 1;
+        self.samples = end;
+
+        self.buffer.clear();
+        if !audible || !chip8.is_sound_playing() {
+            self.phase = 0.0;
+            self.pattern_position = 0.0;
+            self.buffer.resize(len, 0);
+        } else {
+            let amplitude = self.config.volume.clamp(0.0, 1.0) * i16::MAX as f64;
+            let rate = self.config.sample_rate as f64;
+            match chip8.audio_pattern() {
+                Some(pattern) => {
+                    let step = 4000.0 * 2f64.powf((chip8.pitch() as f64 - 64.0) / 48.0) / rate;
+                    for _ in 0..len {
+                        let bit = self.pattern_position as usize;
+                        let high = pattern[bit / 8] & (0x80 >> (bit % 8)) != 0;
+                        self.buffer.push(if high { amplitude as i16 } else { -amplitude as i16 });
+                        self.pattern_position = (self.pattern_position + step) % 128.0;
+                    }
+                },
+                None => {
+                    let step = self.config.frequency / rate;
+                    for _ in 0..len {
+                        self.buffer.push((self.config.waveform.sample(self.phase) * amplitude) as i16);
+                        self.phase = (self.phase + step).fract();
+                    }
+                },
+            }
+        }
+
+        if self.error.is_none() {
+            if let Err(err) = self.sink.write(&self.buffer) {
+                self.error = Some(err);
+            }
+        }
+    }
+
+    // finish ends the output and returns the first error that stopped it
+    pub fn finish(&mut self) -> io::Result<()> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        self.sink.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    struct Recorder(Arc<Mutex<Vec<i16>>>);
+
+    impl AudioSink for Recorder {
+        fn write(&mut self, samples: &[i16]) -> io::Result<()> {
+            self.0.lock().unwrap().extend_from_slice(samples);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn beeps_while_the_sound_timer_runs() {
+        let samples = Arc::new(Mutex::new(Vec::new()));
+        let config = AudioConfig { sample_rate: 8000, frequency: 1000.0, volume: 0.5, waveform: Waveform::Square };
+        let mut beeper = Beeper::new(config, Box::new(Recorder(samples.clone())));
+        let mut chip8 = Chip8::default();
+        chip8.load_rom(vec![0x60, 0x02, 0xF0, 0x18]).unwrap(); // ld v0, 2; ld st, v0
+        beeper.frame(&chip8, true);
+        chip8.next_instruction().unwrap();
+        chip8.next_instruction().unwrap();
+        for _ in 0..3 {
+            beeper.frame(&chip8, true);
+            chip8.timer_tick();
+        }
+
+        // 133.33 samples per frame: 133, 133, 134, 133
+        let samples = samples.lock().unwrap();
+        assert_eq!(samples.len(), 533);
+        assert_eq!(beeper.samples(), 533);
+        assert!(samples[..133].iter().all(|&sample| sample == 0));
+        assert_eq!(&samples[133..141], &[16383, 16383, 16383, 16383, -16383, -16383, -16383, -16383]);
+        assert!(samples[400..].iter().all(|&sample| sample == 0));
+        assert!(samples[133..400].iter().all(|&sample| sample != 0));
+    }
+}
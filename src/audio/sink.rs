@@ -0,0 +1,150 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use super::AudioSink;
+
+// NullSink drops the samples, for headless runs and tests
+#[derive(Debug, Default)]
+pub struct NullSink;
+
+impl AudioSink for NullSink {
+    fn write(&mut self, _samples: &[i16]) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// WavSink writes a 16-bit mono PCM WAV file. The sizes in the header are only right
+// once finish() was called
+pub struct WavSink {
+    out: BufWriter<File>,
+    data_len: u32, // bytes of samples written
+}
+
+impl WavSink {
+    pub fn create(path: &Path, sample_rate: u32) -> io::Result<WavSink> {
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(&wav_header(sample_rate, 0))?;
+        Ok(WavSink { out, data_len: 0 })
+    }
+}
+
+impl AudioSink for WavSink {
+    fn write(&mut self, samples: &[i16]) -> io::Result<()> {
+        for sample in samples {
+            self.out.write_all(&sample.to_le_bytes())?;
+        }
+        self.data_len = self.data_len.saturating_add(samples.len() as u32 * 2);
+        Ok(())
+    }
+
+    // finish writes the sizes into the RIFF and data chunk headers
+    fn finish(&mut self) -> io::Result<()> {
+        self.out.flush()?;
+        let file = self.out.get_mut();
+        file.seek(SeekFrom::Start(4))?;
+        file.write_all(&(36 + self.data_len).to_le_bytes())?;
+        file.seek(SeekFrom::Start(40))?;
+        file.write_all(&self.data_len.to_le_bytes())?;
+        file.seek(SeekFrom::End(0))?;
+        Ok(())
+    }
+}
+
+// wav_header is the RIFF header of a 16-bit mono file with data_len bytes of samples
+pub(crate) fn wav_header(sample_rate: u32, data_len: u32) -> [u8; 44] {
+    let mut fields: Vec<u8> = Vec::with_capacity(44);
+    fields.extend(b"RIFF");
+    fields.extend((36 + data_len).to_le_bytes());
+    fields.extend(b"WAVEfmt ");
+    fields.extend(16u32.to_le_bytes()); // size of the fmt chunk
+    fields.extend(1u16.to_le_bytes()); // PCM
+    fields.extend(1u16.to_le_bytes()); // channels
+    fields.extend(sample_rate.to_le_bytes());
+    fields.extend((sample_rate * 2).to_le_bytes()); // bytes per second
+    fields.extend(2u16.to_le_bytes()); // bytes per sample
+    fields.extend(16u16.to_le_bytes()); // bits per sample
+    fields.extend(b"data");
+    fields.extend(data_len.to_le_bytes());
+    fields.try_into().unwrap()
+}
+
+// read_wav returns the sample rate and the samples of a file in the format WavSink writes
+pub fn read_wav(data: &[u8]) -> Option<(u32, Vec<i16>)> {
+    let header = data.get(..44)?;
+    let sample_rate = u32::from_le_bytes(header[24..28].try_into().ok()?);
+    if header[..4] != *b"RIFF" || header[8..16] != *b"WAVEfmt " || header != wav_header(sample_rate, (data.len() - 44) as u32) {
+        return None;
+    }
+    let samples = data[44..].chunks_exact(2).map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]])).collect();
+    Some((sample_rate, samples))
+}
+
+// DeviceSink plays the samples on the sound card through a player program that reads raw
+// samples from stdin: aplay (ALSA), pacat (PulseAudio) or pw-play (PipeWire), whichever is
+// installed. The player buffers the samples, so the beep is a little late
+pub struct DeviceSink {
+    player: Child,
+    input: Option<ChildStdin>,
+}
+
+impl DeviceSink {
+    pub fn open(sample_rate: u32) -> io::Result<DeviceSink> {
+        let rate = sample_rate.to_string();
+        let players: [(&str, Vec<&str>); 3] = [
+            ("aplay", vec!["-q", "-t", "raw", "-f", "S16_LE", "-c", "1", "-r", &rate, "-"]),
+            ("pacat", vec!["--playback", "--raw", "--format=s16le", "--channels=1", "--rate", &rate]),
+            ("pw-play", vec!["--format", "s16", "--channels", "1", "--rate", &rate, "-"]),
+        ];
+        for (program, args) in players {
+            let spawned = Command::new(program)
+                .args(&args)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn();
+            if let Ok(mut player) = spawned {
+                let input = player.stdin.take();
+                return Ok(DeviceSink { player, input });
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::NotFound, "no audio player found (aplay, pacat or pw-play)"))
+    }
+}
+
+impl AudioSink for DeviceSink {
+    fn write(&mut self, samples: &[i16]) -> io::Result<()> {
+        let Some(input) = &mut self.input else {
+            return Ok(());
+        };
+        let bytes: Vec<u8> = samples.iter().flat_map(|sample| sample.to_le_bytes()).collect();
+        input.write_all(&bytes)
+    }
+
+    // finish closes the pipe, the player stops after the samples it still has
+    fn finish(&mut self) -> io::Result<()> {
+        self.input = None;
+        self.player.wait()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wav_file_has_the_right_sizes() {
+        let path = std::env::temp_dir().join(format!("miko_chip8_{}_beep.wav", std::process::id()));
+        let mut sink = WavSink::create(&path, 22050).unwrap();
+        sink.write(&[1, -2, 3]).unwrap();
+        sink.finish().unwrap();
+        let data = std::fs::read(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(data.len(), 44 + 6);
+        assert_eq!(&data[..44], &wav_header(22050, 6));
+        assert_eq!(&data[4..8], &42u32.to_le_bytes());
+        assert_eq!(&data[44..], &[1, 0, 0xFE, 0xFF, 3, 0]);
+        assert_eq!(read_wav(&data), Some((22050, vec![1, -2, 3])));
+    }
+}
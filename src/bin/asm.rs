@@ -0,0 +1,51 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use miko_chip8emulator::asm::{assemble_file, SYNTAX};
+
+const USAGE: &str = "usage: asm [options] <source>
+
+assembles a program into a ROM that starts at 0x200
+
+options:
+  -o, --output <file>   where to write the ROM (default: the source with a .ch8 extension)
+  -h, --help            show this help and the syntax";
+
+fn main() -> ExitCode {
+    let mut source = None;
+    let mut output = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}\n\nsyntax:\n{}", USAGE, SYNTAX);
+                return ExitCode::SUCCESS;
+            },
+            "-o" | "--output" => output = args.next().map(PathBuf::from),
+            _ if source.is_none() && !arg.starts_with('-') => source = Some(PathBuf::from(arg)),
+            _ => {
+                eprintln!("error: unexpected argument {}\n\n{}", arg, USAGE);
+                return ExitCode::from(2);
+            },
+        }
+    }
+    let Some(source) = source else {
+        eprintln!("error: no source given\n\n{}", USAGE);
+        return ExitCode::from(2);
+    };
+
+    let rom = match assemble_file(&source) {
+        Ok(rom) => rom,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            return ExitCode::FAILURE;
+        },
+    };
+    let output = output.unwrap_or_else(|| source.with_extension("ch8"));
+    if let Err(err) = fs::write(&output, &rom) {
+        eprintln!("error: cannot write {}: {}", output.display(), err);
+        return ExitCode::FAILURE;
+    }
+    println!("{} bytes written to {}", rom.len(), output.display());
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,67 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use miko_chip8emulator::disasm::Disassembly;
+
+const USAGE: &str = "\
+usage: disasm [options] <rom>
+
+prints the ROM as assembly, following jumps and calls from the start to tell code from data
+
+options:
+  -o, --output <file>   write the listing to a file instead of stdout
+  --origin <addr>       address the ROM is loaded at, hexadecimal (default 200)
+  -h, --help            show this help";
+
+fn main() -> ExitCode {
+    let mut rom = None;
+    let mut output = None;
+    let mut origin = 0x200;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return ExitCode::SUCCESS;
+            },
+            "-o" | "--output" => output = args.next().map(PathBuf::from),
+            "--origin" => {
+                let value = args.next().unwrap_or_default();
+                let digits = value.trim_start_matches("0x");
+                match u16::from_str_radix(digits, 16) {
+                    Ok(address) => origin = address,
+                    Err(_) => {
+                        eprintln!("error: invalid origin {}\n\n{}", value, USAGE);
+                        return ExitCode::from(2);
+                    },
+                }
+            },
+            _ if rom.is_none() && !arg.starts_with('-') => rom = Some(PathBuf::from(arg)),
+            _ => {
+                eprintln!("error: unexpected argument {}\n\n{}", arg, USAGE);
+                return ExitCode::from(2);
+            },
+        }
+    }
+    let Some(rom) = rom else {
+        eprintln!("error: no ROM given\n\n{}", USAGE);
+        return ExitCode::from(2);
+    };
+
+    let bytes = match fs::read(&rom) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            eprintln!("error: cannot read ROM {}: {}", rom.display(), err);
+            return ExitCode::FAILURE;
+        },
+    };
+    let listing = Disassembly::new(&bytes, origin).to_string();
+    match output {
+        Some(path) => if let Err(err) = fs::write(&path, listing) {
+            eprintln!("error: cannot write {}: {}", path.display(), err);
+            return ExitCode::FAILURE;
+        },
+        None => print!("{}", listing),
+    }
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,39 @@
+use std::fs;
+use std::io::{self, Write};
+use std::process::ExitCode;
+use miko_chip8emulator::trace::decode_binary;
+
+const USAGE: &str = "usage: tracedump <trace>
+
+prints a trace written with --trace-format binary as text";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let path = match args.as_slice() {
+        [flag] if flag == "-h" || flag == "--help" => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        },
+        [path] => path,
+        _ => {
+            eprintln!("error: expected one trace file\n\n{}", USAGE);
+            return ExitCode::from(2);
+        },
+    };
+    let entries = match fs::read(path).map_err(|err| err.to_string())
+        .and_then(|data| decode_binary(&data).map_err(str::to_string)) {
+        Ok(entries) => entries,
+        Err(err) => {
+            eprintln!("error: cannot read trace {}: {}", path, err);
+            return ExitCode::FAILURE;
+        },
+    };
+    let mut out = io::stdout().lock();
+    for entry in entries {
+        // stop quietly when the reader went away, e.g. head
+        if writeln!(out, "{}", entry).is_err() {
+            break;
+        }
+    }
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,175 @@
+use std::fmt;
+
+pub const LORES_WIDTH: usize = 64;
+pub const LORES_HEIGHT: usize = 32;
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
+pub const PLANE_COUNT: usize = 2; // XO-CHIP bit planes
+
+// Screen is a view of the framebuffer in the currently active resolution,
+// pixels are stored row by row, width * height bytes.
+// every pixel is a bitmask of the XO-CHIP planes it is lit on: 0 is off, 1 is the first plane,
+// 2 the second one and 3 both, plain CHIP-8 programs only ever produce 0 and 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Screen<'a> {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: &'a [u8],
+}
+
+impl Screen<'_> {
+    // pixel returns the value at (x, y)
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        self.pixels[x + y * self.width]
+    }
+}
+
+// TEXT_PIXELS are the characters used for the pixel values when a screen is printed as text
+pub const TEXT_PIXELS: [char; 4] = ['.', '#', 'o', '@'];
+
+// Screen is printed as text art, one line per row
+impl fmt::Display for Screen<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for row in self.pixels.chunks(self.width) {
+            let line: String = row.iter().map(|pixel| TEXT_PIXELS[(*pixel & 0x3) as usize]).collect();
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+// Display is the framebuffer, big enough for the SUPER-CHIP 128x64 mode.
+// In 64x32 mode only the first 64 * 32 bytes are used
+pub(crate) struct Display {
+    hires: bool,
+    planes: u8, // bitmask of the planes selected by Fn01, clear, scroll and draw only touch them
+    pixels: [u8; HIRES_WIDTH * HIRES_HEIGHT],
+}
+
+impl Display {
+    pub(crate) fn new() -> Display {
+        Display {
+            hires: false,
+            planes: 1,
+            pixels: [0; HIRES_WIDTH * HIRES_HEIGHT],
+        }
+    }
+
+    pub(crate) fn width(&self) -> usize {
+        if self.hires { HIRES_WIDTH } else { LORES_WIDTH }
+    }
+
+    pub(crate) fn height(&self) -> usize {
+        if self.hires { HIRES_HEIGHT } else { LORES_HEIGHT }
+    }
+
+    pub(crate) fn screen(&self) -> Screen<'_> {
+        let (width, height) = (self.width(), self.height());
+        Screen { width, height, pixels: &self.pixels[..width * height] }
+    }
+
+    pub(crate) fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    // set_hires switches resolution, every plane is cleared like Octo does
+    pub(crate) fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.pixels = [0; HIRES_WIDTH * HIRES_HEIGHT];
+    }
+
+    pub(crate) fn planes(&self) -> u8 {
+        self.planes
+    }
+
+    // raw_pixels returns the whole 128x64 buffer, whatever resolution is active
+    pub(crate) fn raw_pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    // restore puts back a display saved with is_hires, planes and raw_pixels
+    pub(crate) fn restore(&mut self, hires: bool, planes: u8, pixels: &[u8]) {
+        self.hires = hires;
+        self.planes = planes & 0x3;
+        self.pixels.copy_from_slice(pixels);
+    }
+
+    // select_planes chooses the planes the following instructions work on (XO-CHIP)
+    pub(crate) fn select_planes(&mut self, planes: u8) {
+        self.planes = planes & 0x3;
+    }
+
+    // clear turns off the selected planes
+    pub(crate) fn clear(&mut self) {
+        let keep = !self.planes;
+        for pixel in self.pixels.iter_mut() {
+            *pixel &= keep;
+        }
+    }
+
+    // draw xors a sprite onto a single plane and returns true if any pixel was turned off.
+    // every row is `width` bits wide (8 or 16), taken from the most significant bit of the u16
+    pub(crate) fn draw(&mut self, x: usize, y: usize, rows: &[u16], width: usize, clip: bool,
+                       plane: u8) -> bool {
+        let (screen_width, screen_height) = (self.width(), self.height());
+        // the starting position always wraps, the rest of the sprite is either clipped or wrapped
+        let x = x % screen_width;
+        let y = y % screen_height;
+        let mut collision = false;
+        for (i, row) in rows.iter().enumerate() {
+            for j in 0..width {
+                if (row >> (15 - j)) & 0x1 == 0 {
+                    continue;
+                }
+                if clip && (x + j >= screen_width || y + i >= screen_height) {
+                    continue;
+                }
+                let idx = (x + j) % screen_width + (y + i) % screen_height * screen_width;
+                if self.pixels[idx] & plane != 0 {
+                    collision = true;
+                }
+                self.pixels[idx] ^= plane;
+            }
+        }
+        collision
+    }
+
+    // shift moves the selected planes by (dx, dy) pixels, uncovered pixels are blank
+    fn shift(&mut self, dx: isize, dy: isize) {
+        let (width, height) = (self.width() as isize, self.height() as isize);
+        let mask = self.planes;
+        let old = self.pixels;
+        for y in 0..height {
+            for x in 0..width {
+                let (src_x, src_y) = (x - dx, y - dy);
+                let moved = if src_x >= 0 && src_x < width && src_y >= 0 && src_y < height {
+                    old[(src_x + src_y * width) as usize] & mask
+                } else {
+                    0
+                };
+                let idx = (x + y * width) as usize;
+                self.pixels[idx] = (old[idx] & !mask) | moved;
+            }
+        }
+    }
+
+    // scroll_down moves the picture n pixels down, new rows are blank
+    pub(crate) fn scroll_down(&mut self, n: usize) {
+        self.shift(0, n as isize);
+    }
+
+    // scroll_up moves the picture n pixels up, new rows are blank (XO-CHIP)
+    pub(crate) fn scroll_up(&mut self, n: usize) {
+        self.shift(0, -(n as isize));
+    }
+
+    // scroll_right moves the picture n pixels right, new columns are blank
+    pub(crate) fn scroll_right(&mut self, n: usize) {
+        self.shift(n as isize, 0);
+    }
+
+    // scroll_left moves the picture n pixels left, new columns are blank
+    pub(crate) fn scroll_left(&mut self, n: usize) {
+        self.shift(-(n as isize), 0);
+    }
+}
@@ -0,0 +1,92 @@
+use std::fmt;
+
+// Chip8Error describes why the emulator could not execute an instruction.
+// pc is always the address of the faulting instruction, not the one after it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Chip8Error {
+    StackUnderflow { pc: u16, opcode: u16 },
+    StackOverflow { pc: u16, opcode: u16 },
+    MemoryOutOfBounds { pc: u16, opcode: u16, address: usize },
+    InvalidOpcode { pc: u16, opcode: u16 },
+    PcOutOfRange { pc: u16 },
+}
+
+impl Chip8Error {
+    // pc returns the address of the instruction that caused the error
+    pub fn pc(&self) -> u16 {
+        match *self {
+            Chip8Error::StackUnderflow { pc, .. } => pc,
+            Chip8Error::StackOverflow { pc, .. } => pc,
+            Chip8Error::MemoryOutOfBounds { pc, .. } => pc,
+            Chip8Error::InvalidOpcode { pc, .. } => pc,
+            Chip8Error::PcOutOfRange { pc } => pc,
+        }
+    }
+
+    // opcode returns the faulting opcode, if it could be fetched at all
+    pub fn opcode(&self) -> Option<u16> {
+        match *self {
+            Chip8Error::StackUnderflow { opcode, .. } => Some(opcode),
+            Chip8Error::StackOverflow { opcode, .. } => Some(opcode),
+            Chip8Error::MemoryOutOfBounds { opcode, .. } => Some(opcode),
+            Chip8Error::InvalidOpcode { opcode, .. } => Some(opcode),
+            Chip8Error::PcOutOfRange { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Chip8Error::StackUnderflow { pc, opcode } =>
+                write!(f, "stack underflow at {:#05X} (opcode {:04X})", pc, opcode),
+            Chip8Error::StackOverflow { pc, opcode } =>
+                write!(f, "stack overflow at {:#05X} (opcode {:04X})", pc, opcode),
+            Chip8Error::MemoryOutOfBounds { pc, opcode, address } =>
+                write!(f, "memory access out of bounds at {:#05X} (opcode {:04X}, address {:#X})",
+                       pc, opcode, address),
+            Chip8Error::InvalidOpcode { pc, opcode } =>
+                write!(f, "invalid opcode {:04X} at {:#05X}", opcode, pc),
+            Chip8Error::PcOutOfRange { pc } =>
+                write!(f, "program counter out of range: {:#X}", pc),
+        }
+    }
+}
+
+impl std::error::Error for Chip8Error {}
+
+// RomTooLarge is returned by load_rom when the program doesn't fit between 0x200 and the end of memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RomTooLarge {
+    pub size: usize,
+    pub max: usize,
+}
+
+impl fmt::Display for RomTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ROM is {} bytes, but only {} bytes fit in memory", self.size, self.max)
+    }
+}
+
+impl std::error::Error for RomTooLarge {}
+
+// Fault is what instruction handlers report; next_instruction attaches pc and opcode to it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Fault {
+    StackUnderflow,
+    StackOverflow,
+    MemoryOutOfBounds(usize),
+    InvalidOpcode,
+}
+
+impl Fault {
+    pub(crate) fn at(self, pc: u16, opcode: u16) -> Chip8Error {
+        match self {
+            Fault::StackUnderflow => Chip8Error::StackUnderflow { pc, opcode },
+            Fault::StackOverflow => Chip8Error::StackOverflow { pc, opcode },
+            Fault::MemoryOutOfBounds(address) =>
+                Chip8Error::MemoryOutOfBounds { pc, opcode, address },
+            Fault::InvalidOpcode => Chip8Error::InvalidOpcode { pc, opcode },
+        }
+    }
+}
@@ -0,0 +1,312 @@
+use std::fmt;
+
+// Instruction is a decoded opcode. Registers are indexes from 0 to F, the names and
+// the text form follow Cowgod's reference, extended with SUPER-CHIP and XO-CHIP opcodes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    Sys(u16), // 0nnn, machine code routine, ignored
+    Cls, // 00E0
+    Ret, // 00EE
+    ScrollDown(u8), // 00Cn (SUPER-CHIP)
+    ScrollUp(u8), // 00Dn (XO-CHIP)
+    ScrollRight, // 00FB (SUPER-CHIP)
+    ScrollLeft, // 00FC (SUPER-CHIP)
+    Exit, // 00FD (SUPER-CHIP)
+    Low, // 00FE (SUPER-CHIP)
+    High, // 00FF (SUPER-CHIP)
+    Jump(u16), // 1nnn
+    Call(u16), // 2nnn
+    SkipEqualByte(u8, u8), // 3xkk
+    SkipNotEqualByte(u8, u8), // 4xkk
+    SkipEqual(u8, u8), // 5xy0
+    SaveRange(u8, u8), // 5xy2 (XO-CHIP)
+    LoadRange(u8, u8), // 5xy3 (XO-CHIP)
+    LoadByte(u8, u8), // 6xkk
+    AddByte(u8, u8), // 7xkk
+    Load(u8, u8), // 8xy0
+    Or(u8, u8), // 8xy1
+    And(u8, u8), // 8xy2
+    Xor(u8, u8), // 8xy3
+    Add(u8, u8), // 8xy4
+    Sub(u8, u8), // 8xy5
+    ShiftRight(u8, u8), // 8xy6
+    SubN(u8, u8), // 8xy7
+    ShiftLeft(u8, u8), // 8xyE
+    SkipNotEqual(u8, u8), // 9xy0
+    LoadIndex(u16), // Annn
+    JumpOffset(u16), // Bnnn, the high nibble of nnn is the register used with the jump quirk
+    Random(u8, u8), // Cxkk
+    Draw(u8, u8, u8), // Dxyn
+    SkipKey(u8), // Ex9E
+    SkipNotKey(u8), // ExA1
+    LongIndex(u16), // F000 nnnn (XO-CHIP), the address is the word after the opcode
+    Plane(u8), // Fn01 (XO-CHIP)
+    Audio, // F002 (XO-CHIP)
+    GetDelay(u8), // Fx07
+    WaitKey(u8), // Fx0A
+    SetDelay(u8), // Fx15
+    SetSound(u8), // Fx18
+    AddIndex(u8), // Fx1E
+    Font(u8), // Fx29
+    BigFont(u8), // Fx30 (SUPER-CHIP)
+    Bcd(u8), // Fx33
+    Pitch(u8), // Fx3A (XO-CHIP)
+    Store(u8), // Fx55
+    Restore(u8), // Fx65
+    SaveFlags(u8), // Fx75 (SUPER-CHIP)
+    LoadFlags(u8), // Fx85 (SUPER-CHIP)
+}
+
+impl Instruction {
+    // decode returns None for opcodes that are not instructions.
+    // F000 decodes to LongIndex(0), decode_at() also reads the address that follows it
+    pub fn decode(opcode: u16) -> Option<Instruction> {
+        let x = ((opcode & 0x0F00) >> 8) as u8;
+        let y = ((opcode & 0x00F0) >> 4) as u8;
+        let n = (opcode & 0x000F) as u8;
+        let kk = (opcode & 0x00FF) as u8;
+        let nnn = opcode & 0x0FFF;
+        let instruction = match opcode & 0xF000 {
+            0x0000 => match opcode {
+                0x00E0 => Instruction::Cls,
+                0x00EE => Instruction::Ret,
+                0x00C0..=0x00CF => Instruction::ScrollDown(n),
+                0x00D0..=0x00DF => Instruction::ScrollUp(n),
+                0x00FB => Instruction::ScrollRight,
+                0x00FC => Instruction::ScrollLeft,
+                0x00FD => Instruction::Exit,
+                0x00FE => Instruction::Low,
+                0x00FF => Instruction::High,
+                _      => Instruction::Sys(nnn),
+            },
+            0x1000 => Instruction::Jump(nnn),
+            0x2000 => Instruction::Call(nnn),
+            0x3000 => Instruction::SkipEqualByte(x, kk),
+            0x4000 => Instruction::SkipNotEqualByte(x, kk),
+            0x5000 => match n {
+                0x0 => Instruction::SkipEqual(x, y),
+                0x2 => Instruction::SaveRange(x, y),
+                0x3 => Instruction::LoadRange(x, y),
+                _   => return None,
+            },
+            0x6000 => Instruction::LoadByte(x, kk),
+            0x7000 => Instruction::AddByte(x, kk),
+            0x8000 => match n {
+                0x0 => Instruction::Load(x, y),
+                0x1 => Instruction::Or(x, y),
+                0x2 => Instruction::And(x, y),
+                0x3 => Instruction::Xor(x, y),
+                0x4 => Instruction::Add(x, y),
+                0x5 => Instruction::Sub(x, y),
+                0x6 => Instruction::ShiftRight(x, y),
+                0x7 => Instruction::SubN(x, y),
+                0xE => Instruction::ShiftLeft(x, y),
+                _   => return None,
+            },
+            0x9000 if n == 0 => Instruction::SkipNotEqual(x, y),
+            0xA000 => Instruction::LoadIndex(nnn),
+            0xB000 => Instruction::JumpOffset(nnn),
+            0xC000 => Instruction::Random(x, kk),
+            0xD000 => Instruction::Draw(x, y, n),
+            0xE000 => match kk {
+                0x9E => Instruction::SkipKey(x),
+                0xA1 => Instruction::SkipNotKey(x),
+                _    => return None,
+            },
+            0xF000 => match kk {
+                0x00 if x == 0 => Instruction::LongIndex(0),
+                0x01 => Instruction::Plane(x),
+                0x02 if x == 0 => Instruction::Audio,
+                0x07 => Instruction::GetDelay(x),
+                0x0A => Instruction::WaitKey(x),
+                0x15 => Instruction::SetDelay(x),
+                0x18 => Instruction::SetSound(x),
+                0x1E => Instruction::AddIndex(x),
+                0x29 => Instruction::Font(x),
+                0x30 => Instruction::BigFont(x),
+                0x33 => Instruction::Bcd(x),
+                0x3A => Instruction::Pitch(x),
+                0x55 => Instruction::Store(x),
+                0x65 => Instruction::Restore(x),
+                0x75 => Instruction::SaveFlags(x),
+                0x85 => Instruction::LoadFlags(x),
+                _    => return None,
+            },
+            _ => return None,
+        };
+        Some(instruction)
+    }
+
+    // decode_at decodes the instruction at address, None if it is not an instruction
+    // or runs past the end of memory
+    pub fn decode_at(memory: &[u8], address: usize) -> Option<Instruction> {
+        let word = |at: usize| -> Option<u16> {
+            Some(((*memory.get(at)? as u16) << 8) | *memory.get(at + 1)? as u16)
+        };
+        match Instruction::decode(word(address)?)? {
+            Instruction::LongIndex(_) => Some(Instruction::LongIndex(word(address + 2)?)),
+            instruction => Some(instruction),
+        }
+    }
+
+    // size returns the length of the instruction in bytes
+    pub fn size(&self) -> usize {
+        match self {
+            Instruction::LongIndex(_) => 4,
+            _ => 2,
+        }
+    }
+
+    // encode returns the opcode, for LongIndex the address has to be written after it
+    pub fn encode(&self) -> u16 {
+        let xy = |op: u16, x: u8, y: u8, n: u16| op | (x as u16) << 8 | (y as u16) << 4 | n;
+        let xkk = |op: u16, x: u8, kk: u8| op | (x as u16) << 8 | kk as u16;
+        match *self {
+            Instruction::Sys(nnn) => nnn & 0x0FFF,
+            Instruction::Cls => 0x00E0,
+            Instruction::Ret => 0x00EE,
+            Instruction::ScrollDown(n) => 0x00C0 | (n as u16 & 0xF),
+            Instruction::ScrollUp(n) => 0x00D0 | (n as u16 & 0xF),
+            Instruction::ScrollRight => 0x00FB,
+            Instruction::ScrollLeft => 0x00FC,
+            Instruction::Exit => 0x00FD,
+            Instruction::Low => 0x00FE,
+            Instruction::High => 0x00FF,
+            Instruction::Jump(nnn) => 0x1000 | (nnn & 0x0FFF),
+            Instruction::Call(nnn) => 0x2000 | (nnn & 0x0FFF),
+            Instruction::SkipEqualByte(x, kk) => xkk(0x3000, x, kk),
+            Instruction::SkipNotEqualByte(x, kk) => xkk(0x4000, x, kk),
+            Instruction::SkipEqual(x, y) => xy(0x5000, x, y, 0x0),
+            Instruction::SaveRange(x, y) => xy(0x5000, x, y, 0x2),
+            Instruction::LoadRange(x, y) => xy(0x5000, x, y, 0x3),
+            Instruction::LoadByte(x, kk) => xkk(0x6000, x, kk),
+            Instruction::AddByte(x, kk) => xkk(0x7000, x, kk),
+            Instruction::Load(x, y) => xy(0x8000, x, y, 0x0),
+            Instruction::Or(x, y) => xy(0x8000, x, y, 0x1),
+            Instruction::And(x, y) => xy(0x8000, x, y, 0x2),
+            Instruction::Xor(x, y) => xy(0x8000, x, y, 0x3),
+            Instruction::Add(x, y) => xy(0x8000, x, y, 0x4),
+            Instruction::Sub(x, y) => xy(0x8000, x, y, 0x5),
+            Instruction::ShiftRight(x, y) => xy(0x8000, x, y, 0x6),
+            Instruction::SubN(x, y) => xy(0x8000, x, y, 0x7),
+            Instruction::ShiftLeft(x, y) => xy(0x8000, x, y, 0xE),
+            Instruction::SkipNotEqual(x, y) => xy(0x9000, x, y, 0x0),
+            Instruction::LoadIndex(nnn) => 0xA000 | (nnn & 0x0FFF),
+            Instruction::JumpOffset(nnn) => 0xB000 | (nnn & 0x0FFF),
+            Instruction::Random(x, kk) => xkk(0xC000, x, kk),
+            Instruction::Draw(x, y, n) => xy(0xD000, x, y, n as u16 & 0xF),
+            Instruction::SkipKey(x) => xkk(0xE000, x, 0x9E),
+            Instruction::SkipNotKey(x) => xkk(0xE000, x, 0xA1),
+            Instruction::LongIndex(_) => 0xF000,
+            Instruction::Plane(n) => xkk(0xF000, n, 0x01),
+            Instruction::Audio => 0xF002,
+            Instruction::GetDelay(x) => xkk(0xF000, x, 0x07),
+            Instruction::WaitKey(x) => xkk(0xF000, x, 0x0A),
+            Instruction::SetDelay(x) => xkk(0xF000, x, 0x15),
+            Instruction::SetSound(x) => xkk(0xF000, x, 0x18),
+            Instruction::AddIndex(x) => xkk(0xF000, x, 0x1E),
+            Instruction::Font(x) => xkk(0xF000, x, 0x29),
+            Instruction::BigFont(x) => xkk(0xF000, x, 0x30),
+            Instruction::Bcd(x) => xkk(0xF000, x, 0x33),
+            Instruction::Pitch(x) => xkk(0xF000, x, 0x3A),
+            Instruction::Store(x) => xkk(0xF000, x, 0x55),
+            Instruction::Restore(x) => xkk(0xF000, x, 0x65),
+            Instruction::SaveFlags(x) => xkk(0xF000, x, 0x75),
+            Instruction::LoadFlags(x) => xkk(0xF000, x, 0x85),
+        }
+    }
+
+    // bytes returns the encoded instruction, 2 or 4 bytes long
+    pub fn bytes(&self) -> Vec<u8> {
+        let mut bytes = self.encode().to_be_bytes().to_vec();
+        if let Instruction::LongIndex(address) = *self {
+            bytes.extend(address.to_be_bytes());
+        }
+        bytes
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Instruction::Sys(nnn) => write!(f, "SYS {:#05X}", nnn),
+            Instruction::Cls => write!(f, "CLS"),
+            Instruction::Ret => write!(f, "RET"),
+            Instruction::ScrollDown(n) => write!(f, "SCD {}", n),
+            Instruction::ScrollUp(n) => write!(f, "SCU {}", n),
+            Instruction::ScrollRight => write!(f, "SCR"),
+            Instruction::ScrollLeft => write!(f, "SCL"),
+            Instruction::Exit => write!(f, "EXIT"),
+            Instruction::Low => write!(f, "LOW"),
+            Instruction::High => write!(f, "HIGH"),
+            Instruction::Jump(nnn) => write!(f, "JP {:#05X}", nnn),
+            Instruction::Call(nnn) => write!(f, "CALL {:#05X}", nnn),
+            Instruction::SkipEqualByte(x, kk) => write!(f, "SE V{:X}, {:#04X}", x, kk),
+            Instruction::SkipNotEqualByte(x, kk) => write!(f, "SNE V{:X}, {:#04X}", x, kk),
+            Instruction::SkipEqual(x, y) => write!(f, "SE V{:X}, V{:X}", x, y),
+            Instruction::SaveRange(x, y) => write!(f, "SAVE V{:X}, V{:X}", x, y),
+            Instruction::LoadRange(x, y) => write!(f, "LOAD V{:X}, V{:X}", x, y),
+            Instruction::LoadByte(x, kk) => write!(f, "LD V{:X}, {:#04X}", x, kk),
+            Instruction::AddByte(x, kk) => write!(f, "ADD V{:X}, {:#04X}", x, kk),
+            Instruction::Load(x, y) => write!(f, "LD V{:X}, V{:X}", x, y),
+            Instruction::Or(x, y) => write!(f, "OR V{:X}, V{:X}", x, y),
+            Instruction::And(x, y) => write!(f, "AND V{:X}, V{:X}", x, y),
+            Instruction::Xor(x, y) => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Instruction::Add(x, y) => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Instruction::Sub(x, y) => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Instruction::ShiftRight(x, y) => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Instruction::SubN(x, y) => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Instruction::ShiftLeft(x, y) => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Instruction::SkipNotEqual(x, y) => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Instruction::LoadIndex(nnn) => write!(f, "LD I, {:#05X}", nnn),
+            Instruction::JumpOffset(nnn) => write!(f, "JP V0, {:#05X}", nnn),
+            Instruction::Random(x, kk) => write!(f, "RND V{:X}, {:#04X}", x, kk),
+            Instruction::Draw(x, y, n) => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Instruction::SkipKey(x) => write!(f, "SKP V{:X}", x),
+            Instruction::SkipNotKey(x) => write!(f, "SKNP V{:X}", x),
+            Instruction::LongIndex(nnnn) => write!(f, "LD I, LONG {:#06X}", nnnn),
+            Instruction::Plane(n) => write!(f, "PLANE {}", n),
+            Instruction::Audio => write!(f, "AUDIO"),
+            Instruction::GetDelay(x) => write!(f, "LD V{:X}, DT", x),
+            Instruction::WaitKey(x) => write!(f, "LD V{:X}, K", x),
+            Instruction::SetDelay(x) => write!(f, "LD DT, V{:X}", x),
+            Instruction::SetSound(x) => write!(f, "LD ST, V{:X}", x),
+            Instruction::AddIndex(x) => write!(f, "ADD I, V{:X}", x),
+            Instruction::Font(x) => write!(f, "LD F, V{:X}", x),
+            Instruction::BigFont(x) => write!(f, "LD HF, V{:X}", x),
+            Instruction::Bcd(x) => write!(f, "LD B, V{:X}", x),
+            Instruction::Pitch(x) => write!(f, "PITCH V{:X}", x),
+            Instruction::Store(x) => write!(f, "LD [I], V{:X}", x),
+            Instruction::Restore(x) => write!(f, "LD V{:X}, [I]", x),
+            Instruction::SaveFlags(x) => write!(f, "LD R, V{:X}", x),
+            Instruction::LoadFlags(x) => write!(f, "LD V{:X}, R", x),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_opcode_round_trips() {
+        for opcode in 0..=0xFFFFu16 {
+            if let Some(instruction) = Instruction::decode(opcode) {
+                assert_eq!(instruction.encode(), opcode, "{}", instruction);
+            }
+        }
+        assert_eq!(Instruction::decode(0x8008), None);
+        assert_eq!(Instruction::decode(0xF100), None);
+    }
+
+    #[test]
+    fn displays_mnemonics() {
+        let text = |opcode| Instruction::decode(opcode).unwrap().to_string();
+        assert_eq!(text(0x00E0), "CLS");
+        assert_eq!(text(0x1234), "JP 0x234");
+        assert_eq!(text(0x6A05), "LD VA, 0x05");
+        assert_eq!(text(0xD015), "DRW V0, V1, 5");
+        assert_eq!(text(0xF355), "LD [I], V3");
+        assert_eq!(Instruction::decode_at(&[0xF0, 0x00, 0x12, 0x34], 0).unwrap().to_string(), "LD I, LONG 0x1234");
+    }
+}
@@ -1,31 +1,100 @@
+mod display;
+mod error;
+mod instruction;
+mod quirks;
+mod random;
+mod state;
+mod timing;
+mod trace;
+
+pub use display::{Screen, HIRES_HEIGHT, HIRES_WIDTH, LORES_HEIGHT, LORES_WIDTH, PLANE_COUNT, TEXT_PIXELS};
+pub use error::{Chip8Error, RomTooLarge};
+pub use instruction::Instruction;
+pub use quirks::{IndexIncrement, MemorySize, Quirks};
+pub use random::{RandomSource, SeededRandom, VipRandom};
+pub use state::StateError;
+pub use timing::{VIP_CYCLES_PER_FRAME, VIP_DISPLAY_CYCLES, VIP_INTERPRETER_CYCLES};
+pub use trace::{Register, TraceEntry, TraceSink};
+use display::Display;
+use error::Fault;
+use trace::Registers;
+
+const STACK_SIZE: usize = 16;
+const FONT_ADDRESS: usize = 0x000; // 4x5 hexadecimal digits
+const BIG_FONT_ADDRESS: usize = 0x050; // 8x10 SUPER-CHIP digits, right after the small ones
+const DEFAULT_PITCH: u8 = 64; // 4000 samples per second
+
+// StepOutcome tells the caller what next_instruction did
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    Executed, // instruction finished, pc points to the next one
+    WaitingForKey, // Fx0A is blocked until a key is pressed, pc was not advanced
+    WaitingForVblank, // Dxyn is blocked until the next timer tick (display_wait quirk), pc was not advanced
+    Exited, // 00FD stopped the interpreter, pc stays on it
+}
 
 pub struct Chip8 {
-    memory: [u8; 4096], // 4kB of RAM
+    memory: Vec<u8>, // 4kB of RAM, 64kB on XO-CHIP (see Quirks::memory_size)
     vx:     [u8; 16], // 16 8-bit registers from V0 to VF
     dt:     u8, // 8-bit delay timer
     st:     u8, // 8-bit sound timer
     pc:     u16, // 16-bit program counter (index of current operation from RAM)
-    stack:  [u16; 16], // 16 elements 16-bit stack
+    stack:  [u16; STACK_SIZE], // 16 elements 16-bit stack
     sp:     u8, // 8-bit stack pointer
     i:      u16, // index of sprite
-    gfx:    [u8; 64 * 32], // state of screen
-    pub keyboard: [bool; 16], // true if pressed
+    gfx:    Display, // state of screen, 64x32 or 128x64
+    rpl:    [u8; 16], // SUPER-CHIP RPL user flags saved by Fx75
+    audio_pattern: Option<[u8; 16]>, // XO-CHIP 1-bit audio samples loaded by F002
+    pitch:  u8, // XO-CHIP playback rate of audio_pattern set by Fx3A
+    vblank: bool, // no instruction ran since the last timer tick, Dxyn may draw (display_wait quirk)
+    keyboard: [bool; 16], // true if pressed, changed by key_down and key_up
+    key_presses: u16, // bit n is set when key n went down since Fx0A started waiting
+    waiting_for_key: bool, // the last instruction was Fx0A and it is still waiting
+    quirks: Quirks, // behaviour of ambiguous instructions
+    random: Box<dyn RandomSource>, // bytes for CXNN
+    trace_sink: Option<Box<dyn TraceSink>>, // gets every executed instruction, off by default
+}
+
+impl Default for Chip8 {
+    fn default() -> Self {
+        Chip8::new(Quirks::default())
+    }
 }
 
 impl Chip8 {
-    // new creates a new Chip8 instance
-    pub fn new() -> Chip8 {
+    // new creates a new Chip8 instance that interprets ambiguous instructions according to quirks.
+    // CXNN is seeded randomly, see with_seed for reproducible runs
+    pub fn new(quirks: Quirks) -> Chip8 {
+        Chip8::with_seed(quirks, rand::random())
+    }
+
+    // with_seed creates a Chip8 whose CXNN gives the same numbers for the same seed
+    pub fn with_seed(quirks: Quirks, seed: u64) -> Chip8 {
+        Chip8::with_random(quirks, Box::new(SeededRandom::new(seed)))
+    }
+
+    // with_random creates a Chip8 that takes the bytes of CXNN from random
+    pub fn with_random(quirks: Quirks, random: Box<dyn RandomSource>) -> Chip8 {
         let mut chip8 = Chip8 {
-            memory:   [0; 4096],
+            memory:   vec![0; quirks.memory_size.bytes()],
             vx:       [0; 16],
             dt:       0,
             st:       0,
             pc:       0x200,
-            stack:    [0; 16],
+            stack:    [0; STACK_SIZE],
             sp:       0,
             i:        0,
-            gfx:      [0; 64 * 32],
-            keyboard: [false; 16]
+            gfx:      Display::new(),
+            rpl:      [0; 16],
+            audio_pattern: None,
+            pitch:    DEFAULT_PITCH,
+            vblank:   true,
+            keyboard: [false; 16],
+            key_presses: 0,
+            waiting_for_key: false,
+            quirks,
+            random,
+            trace_sink: None,
         };
         chip8.load_fonts();
         chip8
@@ -39,13 +108,116 @@ impl Chip8 {
         if self.st > 0 {
             self.st -= 1;
         }
+        self.vblank = true;
+    }
+
+    // key_down presses chip8 key 0 to F, other keys are ignored
+    pub fn key_down(&mut self, key: usize) {
+        if let Some(pressed) = self.keyboard.get_mut(key) {
+            if !*pressed {
+                *pressed = true;
+                self.key_presses |= 1 << key;
+            }
+        }
+    }
+
+    // key_up releases chip8 key 0 to F, other keys are ignored
+    pub fn key_up(&mut self, key: usize) {
+        if let Some(pressed) = self.keyboard.get_mut(key) {
+            *pressed = false;
+        }
+    }
+
+    // keys returns which keys are pressed right now
+    pub fn keys(&self) -> [bool; 16] {
+        self.keyboard
+    }
+
+    // set_keys presses and releases keys to match keys, like key_down and key_up do
+    pub fn set_keys(&mut self, keys: [bool; 16]) {
+        for (key, pressed) in keys.into_iter().enumerate() {
+            if pressed {
+                self.key_down(key);
+            } else {
+                self.key_up(key);
+            }
+        }
+    }
+
+    // quirks returns the behaviour profile this instance was created with
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    // pc returns the address of the next instruction
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    // i returns the index register
+    pub fn i(&self) -> u16 {
+        self.i
+    }
+
+    // registers returns V0 through VF
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.vx
+    }
+
+    // sp returns the stack pointer, the number of return addresses on the stack
+    pub fn sp(&self) -> u8 {
+        self.sp
+    }
+
+    // stack returns the return addresses that are on the stack, the innermost call last
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[..self.sp as usize]
+    }
+
+    // memory returns the whole RAM, fonts and program included
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    // opcode_at returns the two bytes at address as an opcode, if they are inside of memory
+    pub fn opcode_at(&self, address: u16) -> Option<u16> {
+        let address = address as usize;
+        if address + 1 >= self.memory.len() {
+            return None;
+        }
+        Some(((self.memory[address] as u16) << 8) | self.memory[address + 1] as u16)
+    }
+
+    // instruction_at decodes the instruction at address, None if it is not a valid one
+    pub fn instruction_at(&self, address: u16) -> Option<Instruction> {
+        Instruction::decode_at(&self.memory, address as usize)
+    }
+
+    // delay_timer returns the current value of the delay timer
+    pub fn delay_timer(&self) -> u8 {
+        self.dt
+    }
+
+    // sound_timer returns the current value of the sound timer
+    pub fn sound_timer(&self) -> u8 {
+        self.st
     }
 
     pub fn is_sound_playing(&self) -> bool {
         self.st > 0
     }
 
-    // load_fonts loads the hexadecimal fonts into memory (digits from 0 to F)
+    // audio_pattern returns the XO-CHIP sample buffer, None until the program loads one with F002
+    pub fn audio_pattern(&self) -> Option<[u8; 16]> {
+        self.audio_pattern
+    }
+
+    // pitch returns the XO-CHIP pitch register, the pattern plays at 4000 * 2^((pitch - 64) / 48) Hz
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    // load_fonts loads the hexadecimal fonts into memory (digits from 0 to F), small and big
     pub fn load_fonts(&mut self) {
         // each digit is 5 bytes long, every byte is a row on screen
         let d_0: [u8; 5] = [0xF0, 0x90, 0x90, 0x90, 0xF0];
@@ -68,39 +240,72 @@ impl Chip8 {
             [d_0, d_1, d_2, d_3, d_4, d_5, d_6, d_7, d_8, d_9, d_a, d_b, d_c, d_d, d_e, d_f];
         for (i, digit) in digits.iter().enumerate() {
             for (j, row) in digit.iter().enumerate() {
-                self.memory[i * 5 + j] = *row;
+                self.memory[FONT_ADDRESS + i * 5 + j] = *row;
+            }
+        }
+
+        // SUPER-CHIP big digits are 8x10, A to F are the Octo extension
+        let big_digits: [[u8; 10]; 16] = [
+            [0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF],
+            [0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF],
+            [0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF],
+            [0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF],
+            [0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03],
+            [0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF],
+            [0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF],
+            [0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18],
+            [0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF],
+            [0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF],
+            [0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3],
+            [0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC],
+            [0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C],
+            [0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC],
+            [0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF],
+            [0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0],
+        ];
+        for (i, digit) in big_digits.iter().enumerate() {
+            for (j, row) in digit.iter().enumerate() {
+                self.memory[BIG_FONT_ADDRESS + i * 10 + j] = *row;
             }
         }
     }
 
     // load_rom loads the ROM into memory
-    pub fn load_rom(&mut self, rom: Vec<u8>) {
+    pub fn load_rom(&mut self, rom: Vec<u8>) -> Result<(), RomTooLarge> {
         // check if rom is too big
-        if rom.len() > 4096 - 0x200 {
-            panic!("ROM is too big to fit in memory");
+        let max = self.memory.len() - 0x200;
+        if rom.len() > max {
+            return Err(RomTooLarge { size: rom.len(), max });
         }
         for (i, byte) in rom.iter().enumerate() {
             self.memory[0x200 + i] = *byte;
         }
+        Ok(())
+    }
+
+    // get_screen returns the current state of the screen together with its resolution
+    pub fn get_screen(&self) -> Screen<'_> {
+        self.gfx.screen()
     }
 
-    // get_screen returns the current state of the screen
-    pub fn get_screen(&self) -> &[u8] {
-        &self.gfx
+    // is_hires returns true while the SUPER-CHIP 128x64 mode is active
+    pub fn is_hires(&self) -> bool {
+        self.gfx.is_hires()
     }
 
     // clear_screen clears the screen
     fn clear_screen(&mut self) {
-        self.gfx = [0; 64 * 32];
+        self.gfx.clear();
     }
 
     // return from subroutine
-    fn ret(&mut self) {
+    fn ret(&mut self) -> Result<(), Fault> {
         if self.sp == 0 {
-            panic!("Stack underflow");
+            return Err(Fault::StackUnderflow);
         }
-        self.pc = self.stack[self.sp as usize];
         self.sp -= 1;
+        self.pc = self.stack[self.sp as usize];
+        Ok(())
     }
 
     // jump to address
@@ -109,256 +314,637 @@ impl Chip8 {
     }
 
     // call a subroutine at address
-    fn call(&mut self, address: u16) {
-        self.sp += 1;
+    fn call(&mut self, address: u16) -> Result<(), Fault> {
+        if self.sp as usize >= STACK_SIZE {
+            return Err(Fault::StackOverflow);
+        }
         self.stack[self.sp as usize] = self.pc;
+        self.sp += 1;
         self.pc = address;
+        Ok(())
+    }
+
+    // reset_vf clears VF after logic instructions if the quirk asks for it
+    fn reset_vf(&mut self) {
+        if self.quirks.vf_reset {
+            self.vx[0xf] = 0;
+        }
+    }
+
+    // increment_index moves I after Fx55/Fx65 stored or loaded registers V0 through Vx
+    fn increment_index(&mut self, x: usize) {
+        match self.quirks.index_increment {
+            IndexIncrement::XPlusOne => self.i = self.i.wrapping_add(x as u16 + 1),
+            IndexIncrement::X => self.i = self.i.wrapping_add(x as u16),
+            IndexIncrement::Unchanged => (),
+        }
+    }
+
+    // check_memory makes sure that len bytes starting at address are inside of memory
+    fn check_memory(&self, address: usize, len: usize) -> Result<(), Fault> {
+        if address + len > self.memory.len() {
+            // report the first byte that doesn't fit
+            return Err(Fault::MemoryOutOfBounds(address.max(self.memory.len())));
+        }
+        Ok(())
+    }
+
+    // skip jumps over the next instruction, the 4 byte long F000 nnnn (XO-CHIP) included
+    fn skip(&mut self) {
+        let pc = self.pc as usize;
+        let long = pc + 1 < self.memory.len() && self.memory[pc] == 0xF0 && self.memory[pc + 1] == 0x00;
+        self.pc = self.pc.wrapping_add(if long { 4 } else { 2 });
     }
 
     // skip next instruction if Vx == byte
     fn se(&mut self, byte1: u8, byte2: u8) {
         if byte1 == byte2 {
-            self.pc += 2;
+            self.skip();
         }
     }
 
     // skip next instruction if Vx != byte
     fn sne(&mut self, byte1: u8, byte2: u8) {
         if byte1 != byte2 {
-            self.pc += 2;
+            self.skip();
+        }
+    }
+
+    // register_range returns the registers from x to y, in reverse order if x > y (XO-CHIP)
+    fn register_range(x: usize, y: usize) -> Vec<usize> {
+        if x <= y {
+            (x..=y).collect()
+        } else {
+            (y..=x).rev().collect()
+        }
+    }
+
+
+    // set_trace_sink starts sending every executed instruction to sink, None stops it
+    pub fn set_trace_sink(&mut self, sink: Option<Box<dyn TraceSink>>) {
+        self.trace_sink = sink;
+    }
+
+    // take_trace_sink stops tracing and gives the sink back, e.g. to flush it
+    pub fn take_trace_sink(&mut self) -> Option<Box<dyn TraceSink>> {
+        self.trace_sink.take()
+    }
+
+    pub fn flush_trace(&mut self) {
+        if let Some(sink) = &mut self.trace_sink {
+            sink.flush();
         }
     }
 
+    fn trace_registers(&self) -> Registers {
+        Registers { vx: self.vx, i: self.i, dt: self.dt, st: self.st, sp: self.sp }
+    }
 
     // execute next instruction
-    pub fn next_instruction(&mut self) {
+    pub fn next_instruction(&mut self) -> Result<StepOutcome, Chip8Error> {
+        if self.trace_sink.is_none() {
+            return self.fetch_and_execute();
+        }
+        let pc = self.pc;
+        let before = self.trace_registers();
+        let opcode = self.opcode_at(pc);
+        let instruction = self.instruction_at(pc);
+        let outcome = self.fetch_and_execute();
+        if let Some(opcode) = opcode {
+            let entry = TraceEntry {
+                pc,
+                opcode,
+                instruction,
+                changes: before.changes(&self.trace_registers()),
+                outcome: outcome.clone(),
+            };
+            if let Some(sink) = &mut self.trace_sink {
+                sink.record(&entry);
+            }
+        }
+        outcome
+    }
+
+    fn fetch_and_execute(&mut self) -> Result<StepOutcome, Chip8Error> {
+        let pc = self.pc;
+        if pc as usize + 1 >= self.memory.len() {
+            return Err(Chip8Error::PcOutOfRange { pc });
+        }
         let instruction: u16 =
-            ((self.memory[self.pc as usize] as u16) << 8) |
-                self.memory[(self.pc + 1) as usize] as u16;
-        self.pc += 2;
-        let opcode = instruction & 0xF000;
-        match opcode {
-            0x0000 => match instruction {
-                0x00E0 => self.clear_screen(),
-                0x00EE => self.ret(),
-                _      => (),
+            ((self.memory[pc as usize] as u16) << 8) |
+                self.memory[pc as usize + 1] as u16;
+        self.pc = self.pc.wrapping_add(2);
+        let outcome = self.execute(instruction).map_err(|fault| {
+            // leave pc on the faulting instruction so the state can be inspected
+            self.pc = pc;
+            fault.at(pc, instruction)
+        })?;
+        if outcome != StepOutcome::WaitingForVblank {
+            self.vblank = false;
+        }
+        self.waiting_for_key = outcome == StepOutcome::WaitingForKey;
+        Ok(outcome)
+    }
+
+    // execute runs a single already fetched instruction
+    fn execute(&mut self, opcode: u16) -> Result<StepOutcome, Fault> {
+        let instruction = Instruction::decode(opcode).ok_or(Fault::InvalidOpcode)?;
+        match instruction {
+            Instruction::Cls => self.clear_screen(),
+            Instruction::Ret => self.ret()?,
+            // scroll display n lines down (SUPER-CHIP) or up (XO-CHIP)
+            Instruction::ScrollDown(n) => self.gfx.scroll_down(n as usize),
+            Instruction::ScrollUp(n) => self.gfx.scroll_up(n as usize),
+            // scroll display 4 pixels right or left (SUPER-CHIP)
+            Instruction::ScrollRight => self.gfx.scroll_right(4),
+            Instruction::ScrollLeft => self.gfx.scroll_left(4),
+            // exit the interpreter (SUPER-CHIP)
+            Instruction::Exit => {
+                self.pc = self.pc.wrapping_sub(2);
+                return Ok(StepOutcome::Exited);
             },
-            0x1000 => {
-                let addr= instruction & 0x0FFF;
-                self.jump(addr);
-            }
-            0x2000 => {
-                let address = instruction & 0x0FFF;
-                self.call(address);
+            // disable and enable 128x64 mode (SUPER-CHIP)
+            Instruction::Low => self.gfx.set_hires(false),
+            Instruction::High => self.gfx.set_hires(true),
+            // 0nnn (SYS addr) jumps to a machine code routine, modern interpreters ignore it
+            Instruction::Sys(_) => (),
+            Instruction::Jump(address) => self.jump(address),
+            Instruction::Call(address) => self.call(address)?,
+            Instruction::SkipEqualByte(x, byte) => self.se(self.vx[x as usize], byte),
+            Instruction::SkipNotEqualByte(x, byte) => self.sne(self.vx[x as usize], byte),
+            // skip next instruction if Vx == Vy
+            Instruction::SkipEqual(x, y) => self.se(self.vx[x as usize], self.vx[y as usize]),
+
+            // store registers Vx through Vy in memory starting at location I (XO-CHIP)
+            Instruction::SaveRange(x, y) => {
+                let registers = Chip8::register_range(x as usize, y as usize);
+                self.check_memory(self.i as usize, registers.len())?;
+                for (offset, register) in registers.into_iter().enumerate() {
+                    self.memory[self.i as usize + offset] = self.vx[register];
+                }
             },
-            0x3000 => {
-                let x = ((instruction & 0x0F00) >> 8) as usize;
-                let byte = (instruction & 0x00FF) as u8;
-                self.se(self.vx[x], byte);
+
+            // read registers Vx through Vy from memory starting at location I (XO-CHIP)
+            Instruction::LoadRange(x, y) => {
+                let registers = Chip8::register_range(x as usize, y as usize);
+                self.check_memory(self.i as usize, registers.len())?;
+                for (offset, register) in registers.into_iter().enumerate() {
+                    self.vx[register] = self.memory[self.i as usize + offset];
+                }
             },
-            0x4000 => {
-                let x = ((instruction & 0x0F00) >> 8) as usize;
-                let byte = (instruction & 0x00FF) as u8;
-                self.sne(self.vx[x], byte);
+            Instruction::LoadByte(x, byte) => self.vx[x as usize] = byte,
+            Instruction::AddByte(x, byte) => self.vx[x as usize] = self.vx[x as usize].wrapping_add(byte),
+
+            // set Vx = Vy
+            Instruction::Load(x, y) => self.vx[x as usize] = self.vx[y as usize],
+
+            // set Vx = Vx | Vy
+            Instruction::Or(x, y) => {
+                self.vx[x as usize] |= self.vx[y as usize];
+                self.reset_vf();
             },
-            0x5000 => {
-                if instruction & 0x000F != 0 {
-                    return;
-                }
-                let x = ((instruction & 0x0F00) >> 8) as usize;
-                let y = ((instruction & 0x00F0) >> 4) as usize;
-                self.se(self.vx[x], self.vx[y]);
+
+            // set Vx = Vx & Vy
+            Instruction::And(x, y) => {
+                self.vx[x as usize] &= self.vx[y as usize];
+                self.reset_vf();
             },
-            0x6000 => {
-                let x = ((instruction & 0x0F00) >> 8) as usize;
-                let byte = (instruction & 0x00FF) as u8;
-                self.vx[x] = byte;
+
+            // set Vx = Vx ^ Vy
+            Instruction::Xor(x, y) => {
+                self.vx[x as usize] ^= self.vx[y as usize];
+                self.reset_vf();
             },
-            0x7000 => {
-                let x = ((instruction & 0x0F00) >> 8) as usize;
-                let byte = (instruction & 0x00FF) as u8;
-                self.vx[x] = self.vx[x].wrapping_add(byte);
+
+            // set Vx = Vx + Vy, set VF = carry (if overflow, than VF = 1, else 0)
+            // VF is written last, so the flag wins when x is F
+            Instruction::Add(x, y) => {
+                let (res, overflow) = self.vx[x as usize].overflowing_add(self.vx[y as usize]);
+                self.vx[x as usize] = res;
+                self.vx[0xf] = if overflow { 1 } else { 0 };
             },
-            0x8000 => {
-                let x = ((instruction & 0x0F00) >> 8) as usize;
-                let y = ((instruction & 0x00F0) >> 4) as usize;
-                match instruction & 0x000F {
-                    // set Vx = Vy
-                    0x0000 => self.vx[x] = self.vx[y],
-
-                    // set Vx = Vx | Vy
-                    0x0001 => self.vx[x] |= self.vx[y],
-
-                    // set Vx = Vx & Vy
-                    0x0002 => self.vx[x] &= self.vx[y],
-
-                    // set Vx = Vx ^ Vy
-                    0x0003 => self.vx[x] ^= self.vx[y],
-
-                    // set Vx = Vx + Vy, set VF = carry (if overflow, than VF = 1, else 0)
-                    0x0004 => {
-                        let (res, overflow) = self.vx[x].overflowing_add(self.vx[y]);
-                        self.vx[0xf] = if overflow { 1 } else { 0 };
-                        self.vx[x] = res;
-                    },
 
-                    // set Vx = Vx - Vy, set VF = NOT borrow (if Vx > Vy, then VF = 1, else 0)
-                    0x0005 => {
-                        let (res, overflow) = self.vx[x].overflowing_sub(self.vx[y]);
-                        self.vx[0xf] = if overflow { 0 } else { 1 };
-                        self.vx[x] = res;
-                    },
+            // set Vx = Vx - Vy, set VF = NOT borrow (if Vx > Vy, then VF = 1, else 0)
+            Instruction::Sub(x, y) => {
+                let (res, overflow) = self.vx[x as usize].overflowing_sub(self.vx[y as usize]);
+                self.vx[x as usize] = res;
+                self.vx[0xf] = if overflow { 0 } else { 1 };
+            },
 
-                    // if least-significant bit of Vx is 1, then VF = 1, else 0. Then Vx = Vx >> 1
-                    0x0006 => {
-                        self.vx[0xf] = self.vx[x] & 0x1;
-                        self.vx[x] >>= 1;
-                    },
+            // if least-significant bit of source is 1, then VF = 1, else 0. Then Vx = source >> 1
+            Instruction::ShiftRight(x, y) => {
+                let source = self.vx[if self.quirks.shift_uses_vy { y } else { x } as usize];
+                self.vx[x as usize] = source >> 1;
+                self.vx[0xf] = source & 0x1;
+            },
 
-                    // if Vy > Vx, then VF = 1, else 0. Then Vx = Vy - Vx
-                    0x0007 => {
-                        let (res, overflow) = self.vx[y].overflowing_sub(self.vx[x]);
-                        self.vx[0xf] = if overflow { 0 } else { 1 };
-                        self.vx[x] = res;
-                    },
+            // if Vy > Vx, then VF = 1, else 0. Then Vx = Vy - Vx
+            Instruction::SubN(x, y) => {
+                let (res, overflow) = self.vx[y as usize].overflowing_sub(self.vx[x as usize]);
+                self.vx[x as usize] = res;
+                self.vx[0xf] = if overflow { 0 } else { 1 };
+            },
 
-                    // Vf is set to most significant bit of Vx, then Vx = Vx << 1
-                    0x000E => {
-                        self.vx[0xf] = self.vx[x] >> 7;
-                        self.vx[x] <<= 1;
-                    },
-                    _      => (),
+            // Vf is set to most significant bit of source, then Vx = source << 1
+            Instruction::ShiftLeft(x, y) => {
+                let source = self.vx[if self.quirks.shift_uses_vy { y } else { x } as usize];
+                self.vx[x as usize] = source << 1;
+                self.vx[0xf] = source >> 7;
+            },
+
+            // skip next instruction if Vx != Vy
+            Instruction::SkipNotEqual(x, y) => self.sne(self.vx[x as usize], self.vx[y as usize]),
+
+            // set I = nnn
+            Instruction::LoadIndex(address) => self.i = address,
+
+            // jump to location nnn + V0 (or xnn + Vx with the jump quirk)
+            Instruction::JumpOffset(address) => {
+                let offset = if self.quirks.jump_uses_vx {
+                    self.vx[(address >> 8) as usize]
+                } else {
+                    self.vx[0]
+                };
+                self.jump(address + offset as u16);
+            },
+
+            // set Vx = random byte AND kk
+            Instruction::Random(x, byte) => self.vx[x as usize] = self.random.next_byte(&self.memory) & byte,
+
+            // draw sprite at (Vx, Vy) with width 8 and height n,
+            // n = 0 draws a 16x16 sprite (SUPER-CHIP), two bytes per row
+            // with display_wait it only draws as the first instruction after a timer tick,
+            // like the VIP which waits for the display interrupt, so sprites don't flicker
+            Instruction::Draw(x, y, n) => {
+                if self.quirks.waits_for_vblank() && !self.vblank {
+                    self.pc = self.pc.wrapping_sub(2);
+                    return Ok(StepOutcome::WaitingForVblank);
+                }
+                let vx = self.vx[x as usize] as usize;
+                let vy = self.vx[y as usize] as usize;
+                let n = n as usize;
+
+                // every selected plane (XO-CHIP) takes its own sprite, stored one after another
+                let (height, bytes_per_row) = if n == 0 { (16, 2) } else { (n, 1) };
+                let sprite_len = height * bytes_per_row;
+                let planes = self.gfx.planes();
+                let plane_count = planes.count_ones() as usize;
+                self.check_memory(self.i as usize, sprite_len * plane_count)?;
+
+                let mut collision = false;
+                let mut start = self.i as usize;
+                for plane in [1u8, 2u8] {
+                    if planes & plane == 0 {
+                        continue;
+                    }
+                    let rows: Vec<u16> = self.memory[start..start + sprite_len]
+                        .chunks(bytes_per_row)
+                        .map(|row| if n == 0 {
+                            ((row[0] as u16) << 8) | row[1] as u16
+                        } else {
+                            (row[0] as u16) << 8
+                        })
+                        .collect();
+                    collision |= self.gfx.draw(vx, vy, &rows, bytes_per_row * 8,
+                                               self.quirks.clip_sprites, plane);
+                    start += sprite_len;
                 }
+                self.vx[0xf] = if collision { 1 } else { 0 };
             },
-            0x9000 => {
-                if instruction & 0x000F != 0 {
-                    return;
+
+            // skip next instruction if key with value Vx is pressed
+            Instruction::SkipKey(x) => {
+                let key = self.vx[x as usize];
+                if self.keyboard[key as usize & 0xF] {
+                    self.skip();
                 }
-                // skip next instruction if Vx != Vy
-                let x = ((instruction & 0x0F00) >> 8) as usize;
-                let y = ((instruction & 0x00F0) >> 4) as usize;
-                self.sne(self.vx[x], self.vx[y]);
             },
-            0xA000 => {
-                // set I = nnn
-                let addr = instruction & 0x0FFF;
-                self.i = addr;
+
+            // skip next instruction if key with value Vx is not pressed
+            Instruction::SkipNotKey(x) => {
+                let key = self.vx[x as usize];
+                if !self.keyboard[key as usize & 0xF] {
+                    self.skip();
+                }
             },
-            0xB000 => {
-                // jump to location nnn + V0
-                let addr = instruction & 0x0FFF;
-                self.jump(addr + self.vx[0] as u16);
+
+            // set I = nnnn, the address is the next 2 bytes (XO-CHIP)
+            Instruction::LongIndex(_) => {
+                self.check_memory(self.pc as usize, 2)?;
+                let pc = self.pc as usize;
+                self.i = ((self.memory[pc] as u16) << 8) | self.memory[pc + 1] as u16;
+                self.pc = self.pc.wrapping_add(2);
             },
-            0xC000 => {
-                // set Vx = random byte AND kk
-                let x = ((instruction & 0x0F00) >> 8) as usize;
-                let byte = (instruction & 0x00FF) as u8;
-                self.vx[x] = rand::random::<u8>() & byte;
+
+            // select drawing planes n (XO-CHIP)
+            Instruction::Plane(n) => self.gfx.select_planes(n),
+
+            // load 16 bytes of audio pattern starting at location I (XO-CHIP)
+            Instruction::Audio => {
+                self.check_memory(self.i as usize, 16)?;
+                let mut pattern = [0; 16];
+                pattern.copy_from_slice(&self.memory[self.i as usize..self.i as usize + 16]);
+                self.audio_pattern = Some(pattern);
             },
-            0xD000 => {
-                // draw sprite at (Vx, Vy) with width 8 and height n
-                let vx = self.vx[((instruction & 0x0F00) >> 8) as usize] as usize;
-                let vy = self.vx[((instruction & 0x00F0) >> 4) as usize] as usize;
-                let n = (instruction & 0x000F) as usize;
-
-                self.vx[0xf] = 0;
-
-                for i in 0..n {
-                    let sprite = self.memory[self.i as usize + i];
-                    for j in 0..8 {
-                        let bit = (sprite >> (7 - j)) & 0x1;
-                        let x = (vx + j) % 64;
-                        let y = (vy + i) % 32;
-                        let idx = x + y * 64;
-
-                        if bit == 1 && self.gfx[idx] == 1 {
-                            self.vx[0xf] = 1;
-                        }
-
-                        self.gfx[idx] ^= bit;
-                    }
+
+            // set Vx = delay timer value
+            Instruction::GetDelay(x) => self.vx[x as usize] = self.dt,
+
+            // wait for a key to be pressed and released, store key value in Vx.
+            // a key that was already held when the wait started has to be pressed again
+            Instruction::WaitKey(x) => {
+                if !self.waiting_for_key {
+                    self.key_presses = 0;
                 }
-            },
-            0xE000 => {
-                let x = ((instruction & 0x0F00) >> 8) as usize;
-                match instruction & 0x00FF {
-                    // skip next instruction if key with value Vx is pressed
-                    0x009E => {
-                        let key = self.vx[x];
-                        if self.keyboard[key as usize] == true {
-                            self.pc += 2;
-                        }
-                    },
-                    // skip next instruction if key with value Vx is not pressed
-                    0x00A1 => {
-                        let key = self.vx[x];
-                        if self.keyboard[key as usize] == false {
-                            self.pc += 2;
-                        }
+                let released = (0..16).find(|&key| self.key_presses & 1 << key != 0 && !self.keyboard[key]);
+                match released {
+                    Some(key) if self.waiting_for_key => self.vx[x as usize] = key as u8,
+                    _ => {
+                        self.pc = self.pc.wrapping_sub(2);
+                        return Ok(StepOutcome::WaitingForKey);
                     },
-                    _      => (),
                 }
             },
-            0xF000 => {
-                let x = ((instruction & 0x0F00) >> 8) as usize;
-                match instruction & 0x00FF {
-                    // set Vx = delay timer value
-                    0x0007 => self.vx[x] = self.dt,
-
-                    // wait for key press, store key value in Vx
-                    0x000A => {
-                        let mut key_pressed = false;
-                        for i in 0..16 {
-                            if self.keyboard[i] {
-                                self.vx[x] = i as u8;
-                                key_pressed = true;
-                                break;
-                            }
-                        }
-                        if !key_pressed {
-                            self.pc -= 2;
-                        }
-                    },
 
-                    // set delay timer = Vx
-                    0x0015 => self.dt = self.vx[x],
+            // set delay timer = Vx
+            Instruction::SetDelay(x) => self.dt = self.vx[x as usize],
 
-                    // set sound timer = Vx
-                    0x0018 => self.st = self.vx[x],
+            // set sound timer = Vx
+            Instruction::SetSound(x) => self.st = self.vx[x as usize],
 
-                    // set I = I + Vx
-                    0x001E => self.i += self.vx[x] as u16,
+            // set I = I + Vx
+            Instruction::AddIndex(x) => self.i = self.i.wrapping_add(self.vx[x as usize] as u16),
 
-                    // set I = location of sprite for digit Vx
-                    0x0029 => self.i = self.vx[x] as u16 * 5,
+            // set pitch = Vx (XO-CHIP)
+            Instruction::Pitch(x) => self.pitch = self.vx[x as usize],
 
-                    // store BCD representation of Vx in memory locations I, I+1, I+2
-                    0x0033 => {
-                        let vx = self.vx[x];
-                        self.memory[self.i as usize] = vx / 100;
-                        self.memory[self.i as usize + 1] = (vx / 10) % 10;
-                        self.memory[self.i as usize + 2] = vx % 10;
-                    },
+            // set I = location of sprite for digit Vx
+            Instruction::Font(x) => self.i = (FONT_ADDRESS + (self.vx[x as usize] & 0xF) as usize * 5) as u16,
 
-                    // store registers V0 through Vx in memory starting at location I
-                    0x0055 => {
-                        for i in 0..=x {
-                            self.memory[self.i as usize + i] = self.vx[i];
-                        }
-                        self.i = self.i + ((x + 1) as u16);
-                    },
+            // set I = location of 8x10 sprite for digit Vx (SUPER-CHIP)
+            Instruction::BigFont(x) => self.i = (BIG_FONT_ADDRESS + (self.vx[x as usize] & 0xF) as usize * 10) as u16,
 
-                    // read registers V0 through Vx from memory starting at location I
-                    0x0065 => {
-                        for i in 0..x + 1 {
-                            self.vx[i] = self.memory[self.i as usize + i];
-                        }
-                        self.i = self.i + ((x + 1) as u16);
-                    },
-                    _      => (),
+            // store BCD representation of Vx in memory locations I, I+1, I+2
+            Instruction::Bcd(x) => {
+                self.check_memory(self.i as usize, 3)?;
+                let vx = self.vx[x as usize];
+                self.memory[self.i as usize] = vx / 100;
+                self.memory[self.i as usize + 1] = (vx / 10) % 10;
+                self.memory[self.i as usize + 2] = vx % 10;
+            },
+
+            // store registers V0 through Vx in memory starting at location I
+            Instruction::Store(x) => {
+                let x = x as usize;
+                self.check_memory(self.i as usize, x + 1)?;
+                for i in 0..=x {
+                    self.memory[self.i as usize + i] = self.vx[i];
+                }
+                self.increment_index(x);
+            },
+
+            // read registers V0 through Vx from memory starting at location I
+            Instruction::Restore(x) => {
+                let x = x as usize;
+                self.check_memory(self.i as usize, x + 1)?;
+                for i in 0..x + 1 {
+                    self.vx[i] = self.memory[self.i as usize + i];
                 }
+                self.increment_index(x);
             },
-            _ => (),
+
+            // store registers V0 through Vx in RPL user flags (SUPER-CHIP)
+            Instruction::SaveFlags(x) => self.rpl[..=x as usize].copy_from_slice(&self.vx[..=x as usize]),
+
+            // read registers V0 through Vx from RPL user flags (SUPER-CHIP)
+            Instruction::LoadFlags(x) => self.vx[..=x as usize].copy_from_slice(&self.rpl[..=x as usize]),
         }
+        Ok(StepOutcome::Executed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(rom: &[u8], steps: usize) -> (Chip8, Result<StepOutcome, Chip8Error>) {
+        run_with(Quirks::default(), rom, steps)
+    }
+
+    fn run_with(quirks: Quirks, rom: &[u8], steps: usize) -> (Chip8, Result<StepOutcome, Chip8Error>) {
+        let mut chip8 = Chip8::new(quirks);
+        chip8.load_rom(rom.to_vec()).unwrap();
+        let mut result = Ok(StepOutcome::Executed);
+        for _ in 0..steps {
+            result = chip8.next_instruction();
+            if result.is_err() {
+                break;
+            }
+        }
+        (chip8, result)
+    }
+
+    #[test]
+    fn ret_with_empty_stack_underflows() {
+        let (chip8, result) = run(&[0x00, 0xEE], 1);
+        assert_eq!(result, Err(Chip8Error::StackUnderflow { pc: 0x200, opcode: 0x00EE }));
+        assert_eq!(chip8.pc, 0x200);
+    }
+
+    #[test]
+    fn call_and_return() {
+        // 200: call 206; 202: jp 202; 204: -; 206: ret
+        let (chip8, result) = run(&[0x22, 0x06, 0x12, 0x02, 0x00, 0x00, 0x00, 0xEE], 2);
+        assert_eq!(result, Ok(StepOutcome::Executed));
+        assert_eq!(chip8.pc, 0x202);
+        assert_eq!(chip8.sp, 0);
+    }
+
+    #[test]
+    fn recursive_call_overflows_after_sixteen_levels() {
+        let (chip8, result) = run(&[0x22, 0x00], 17);
+        assert_eq!(result, Err(Chip8Error::StackOverflow { pc: 0x200, opcode: 0x2200 }));
+        assert_eq!(chip8.sp as usize, STACK_SIZE);
+    }
+
+    #[test]
+    fn store_past_end_of_memory_is_reported() {
+        // ld i, 0xFFE; ld [i], v3
+        let (_, result) = run_with(Quirks::cosmac_vip(), &[0xAF, 0xFE, 0xF3, 0x55], 2);
+        assert_eq!(result, Err(Chip8Error::MemoryOutOfBounds {
+            pc: 0x202, opcode: 0xF355, address: 0x1000,
+        }));
+    }
+
+    #[test]
+    fn unknown_opcode_is_reported() {
+        let (_, result) = run(&[0x80, 0x1F], 1);
+        assert_eq!(result, Err(Chip8Error::InvalidOpcode { pc: 0x200, opcode: 0x801F }));
+    }
+
+    #[test]
+    fn running_off_the_end_of_memory() {
+        // jp 0xFFF
+        let (_, result) = run_with(Quirks::cosmac_vip(), &[0x1F, 0xFF], 2);
+        assert_eq!(result, Err(Chip8Error::PcOutOfRange { pc: 0xFFF }));
+    }
+
+    #[test]
+    fn key_wait_reports_blocking() {
+        let (chip8, result) = run(&[0xF0, 0x0A], 1);
+        assert_eq!(result, Ok(StepOutcome::WaitingForKey));
+        assert_eq!(chip8.pc, 0x200);
+    }
+
+    #[test]
+    fn key_wait_at_the_end_of_memory() {
+        // ld v0, k at FFFE, after it pc wrapped around to 0
+        let mut chip8 = Chip8::new(Quirks::modern());
+        chip8.memory[0xFFFE..].copy_from_slice(&[0xF0, 0x0A]);
+        chip8.pc = 0xFFFE;
+        assert_eq!(chip8.next_instruction(), Ok(StepOutcome::WaitingForKey));
+        assert_eq!(chip8.pc, 0xFFFE);
+    }
+
+    #[test]
+    fn key_wait_needs_a_press_and_a_release() {
+        // ld v0, k
+        let mut chip8 = Chip8::default();
+        chip8.load_rom(vec![0xF0, 0x0A]).unwrap();
+        // a key held from before doesn't count, not even when it's released
+        chip8.key_down(3);
+        assert_eq!(chip8.next_instruction(), Ok(StepOutcome::WaitingForKey));
+        chip8.key_up(3);
+        assert_eq!(chip8.next_instruction(), Ok(StepOutcome::WaitingForKey));
+        chip8.key_down(5);
+        assert_eq!(chip8.next_instruction(), Ok(StepOutcome::WaitingForKey));
+        chip8.key_up(5);
+        assert_eq!(chip8.next_instruction(), Ok(StepOutcome::Executed));
+        assert_eq!(chip8.vx[0], 5);
+    }
+
+    #[test]
+    fn keys_past_f_are_ignored() {
+        let mut chip8 = Chip8::default();
+        chip8.key_down(16);
+        chip8.key_down(usize::MAX);
+        assert_eq!(chip8.keys(), [false; 16]);
+        chip8.key_up(16);
+        assert_eq!(chip8.key_presses, 0);
+    }
+
+    #[test]
+    fn display_wait_draws_once_per_frame() {
+        // drw v0, v0, 1; drw v0, v0, 1
+        let quirks = Quirks { display_wait: true, ..Quirks::cosmac_vip() };
+        let (mut chip8, result) = run_with(quirks, &[0xD0, 0x01, 0xD0, 0x01], 2);
+        assert_eq!(result, Ok(StepOutcome::WaitingForVblank));
+        assert_eq!(chip8.pc, 0x202);
+        chip8.timer_tick();
+        assert_eq!(chip8.next_instruction(), Ok(StepOutcome::Executed));
+        assert_eq!(chip8.pc, 0x204);
+    }
+
+    #[test]
+    fn memory_sizes_are_checked() {
+        assert_eq!(MemorySize::new(0x1FF), None);
+        assert_eq!(MemorySize::new(0x10001), None);
+        // the smallest memory holds the fonts and no program
+        let mut chip8 = Chip8::new(Quirks { memory_size: MemorySize::new(0x200).unwrap(), ..Quirks::cosmac_vip() });
+        assert!(chip8.load_rom(Vec::new()).is_ok());
+        assert!(chip8.load_rom(vec![0x00]).is_err());
+    }
+
+    #[test]
+    fn shift_quirk_selects_source_register() {
+        // ld v1, 0x81; shr v0, v1
+        let rom = [0x61, 0x81, 0x80, 0x16];
+        let (vip, _) = run_with(Quirks::cosmac_vip(), &rom, 2);
+        assert_eq!((vip.vx[0], vip.vx[0xf]), (0x40, 1));
+        let (schip, _) = run_with(Quirks::superchip(), &rom, 2);
+        assert_eq!((schip.vx[0], schip.vx[0xf]), (0, 0));
+    }
+
+    #[test]
+    fn index_increment_quirk() {
+        // ld i, 0x300; ld [i], v2
+        let rom = [0xA3, 0x00, 0xF2, 0x55];
+        assert_eq!(run_with(Quirks::cosmac_vip(), &rom, 2).0.i, 0x303);
+        assert_eq!(run_with(Quirks::chip48(), &rom, 2).0.i, 0x302);
+        assert_eq!(run_with(Quirks::superchip(), &rom, 2).0.i, 0x300);
+    }
+
+    #[test]
+    fn sprites_clip_or_wrap_at_the_edge() {
+        // ld v0, 60; ld i, 0x206; drw v0, v0, 1; 0x206: 0xFF -> 4 pixels visible, 4 wrapped
+        let rom = [0x60, 0x3C, 0xA2, 0x06, 0xD0, 0x01, 0xFF];
+        let (clipped, _) = run_with(Quirks::cosmac_vip(), &rom, 3);
+        assert_eq!(lit_pixels(&clipped), 4);
+        let (wrapped, _) = run_with(Quirks::modern(), &rom, 3);
+        assert_eq!(lit_pixels(&wrapped), 8);
+    }
+
+    fn lit_pixels(chip8: &Chip8) -> usize {
+        chip8.get_screen().pixels.iter().filter(|p| **p == 1).count()
+    }
+
+    #[test]
+    fn hires_mode_and_big_sprites() {
+        // hires; ld v0, 120; ld i, 0x20A; drw v0, v0, 0; exit; 0x20A: 32 bytes of 0xFF
+        let mut rom = vec![0x00, 0xFF, 0x60, 0x78, 0xA2, 0x0A, 0xD0, 0x00, 0x00, 0xFD];
+        rom.extend([0xFF; 32]);
+        let (chip8, result) = run(&rom, 5);
+        assert_eq!(result, Ok(StepOutcome::Exited));
+        let screen = chip8.get_screen();
+        assert_eq!((screen.width, screen.height), (128, 64));
+        // x = 120, so 8 columns are visible and 8 wrap to the left edge
+        assert_eq!(lit_pixels(&chip8), 16 * 16);
+        assert_eq!(screen.pixel(127, 120 % 64), 1);
+        assert_eq!(screen.pixel(0, 120 % 64), 1);
+    }
+
+    #[test]
+    fn long_index_load_is_skipped_as_one_instruction() {
+        // se v0, 0; ld i, long 0x1234; ld v1, 1
+        let (chip8, _) = run(&[0x30, 0x00, 0xF0, 0x00, 0x12, 0x34, 0x61, 0x01], 2);
+        assert_eq!(chip8.i, 0);
+        assert_eq!(chip8.vx[1], 1);
+        let (chip8, _) = run(&[0xF0, 0x00, 0x12, 0x34], 1);
+        assert_eq!((chip8.i, chip8.pc), (0x1234, 0x204));
+    }
+
+    #[test]
+    fn both_planes_take_consecutive_sprites() {
+        // plane 3; ld i, 0x208; drw v0, v0, 1; jp 0x206; 0x208: 0xF0 0x3C
+        let rom = [0xF3, 0x01, 0xA2, 0x08, 0xD0, 0x01, 0x12, 0x06, 0xF0, 0x3C];
+        let (chip8, _) = run(&rom, 3);
+        let screen = chip8.get_screen();
+        let row: Vec<u8> = (0..8).map(|x| screen.pixel(x, 0)).collect();
+        assert_eq!(row, [1, 1, 3, 3, 2, 2, 0, 0]);
+    }
+
+    #[test]
+    fn register_range_store_and_load() {
+        // ld v1, 7; ld v2, 8; ld i, 0x300; save v2 - v1; load v3 - v4
+        let rom = [0x61, 0x07, 0x62, 0x08, 0xA3, 0x00, 0x52, 0x12, 0x53, 0x43];
+        let (chip8, _) = run(&rom, 5);
+        assert_eq!(&chip8.memory[0x300..0x302], &[8, 7]);
+        assert_eq!(&chip8.vx[3..5], &[8, 7]);
+    }
+
+    #[test]
+    fn rom_too_large() {
+        let mut chip8 = Chip8::new(Quirks::cosmac_vip());
+        assert_eq!(chip8.load_rom(vec![0; 4000]), Err(RomTooLarge { size: 4000, max: 3584 }));
+    }
+
+    #[test]
+    fn scrolling() {
+        // ld i, font 0; drw v0, v0, 5; scroll down 2; scroll right
+        let (chip8, _) = run(&[0xA0, 0x00, 0xD0, 0x05, 0x00, 0xC2, 0x00, 0xFB], 4);
+        let screen = chip8.get_screen();
+        assert_eq!(screen.pixel(4, 2), 1);
+        assert_eq!(screen.pixel(3, 2), 0);
+        assert_eq!(screen.pixel(4, 1), 0);
     }
 }
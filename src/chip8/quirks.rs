@@ -0,0 +1,160 @@
+// Quirks selects how the instructions that differ between CHIP-8 interpreters behave.
+// The presets follow the behaviour documented for each interpreter by the chip8 test suite.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    pub vf_reset: bool, // 8xy1, 8xy2 and 8xy3 set VF to 0
+    pub shift_uses_vy: bool, // 8xy6 and 8xyE shift Vy into Vx instead of shifting Vx in place
+    pub index_increment: IndexIncrement, // how Fx55 and Fx65 change I
+    pub jump_uses_vx: bool, // Bnnn jumps to nnn + Vx (x is the high nibble of nnn) instead of nnn + V0
+    pub clip_sprites: bool, // sprites are cut at the screen edge instead of wrapping around
+    pub memory_size: MemorySize, // 4kB on classic interpreters, 64kB on XO-CHIP
+    pub vip_timing: bool, // instructions take as long as on the COSMAC VIP instead of --ipf per frame
+    pub display_wait: bool, // Dxyn waits for the next 60Hz frame, so one sprite is drawn per frame
+}
+
+// MemorySize is the RAM of the interpreter: at least the 0x200 bytes below the program, where
+// the fonts are, and at most the 64kB pc and I can address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemorySize(usize);
+
+impl MemorySize {
+    pub const CLASSIC: MemorySize = MemorySize(4096);
+    pub const XO_CHIP: MemorySize = MemorySize(65536);
+
+    // new returns None for sizes outside 0x200 to 0x10000 bytes
+    pub fn new(bytes: usize) -> Option<MemorySize> {
+        (0x200..=0x10000).contains(&bytes).then_some(MemorySize(bytes))
+    }
+
+    pub fn bytes(self) -> usize {
+        self.0
+    }
+}
+
+// IndexIncrement is what happens to I after Fx55 and Fx65
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexIncrement {
+    XPlusOne, // I = I + x + 1, original COSMAC VIP
+    X, // I = I + x, CHIP-48 off-by-one
+    Unchanged, // I is left alone, SUPER-CHIP
+}
+
+impl Quirks {
+    // cosmac_vip is the original interpreter from 1977
+    pub fn cosmac_vip() -> Quirks {
+        Quirks {
+            vf_reset: true,
+            shift_uses_vy: true,
+            index_increment: IndexIncrement::XPlusOne,
+            jump_uses_vx: false,
+            clip_sprites: true,
+            memory_size: MemorySize::CLASSIC,
+            vip_timing: false,
+            display_wait: false,
+        }
+    }
+
+    // chip48 is the HP-48 interpreter most 90s games were written for
+    pub fn chip48() -> Quirks {
+        Quirks {
+            vf_reset: false,
+            shift_uses_vy: false,
+            index_increment: IndexIncrement::X,
+            jump_uses_vx: true,
+            clip_sprites: true,
+            memory_size: MemorySize::CLASSIC,
+            vip_timing: false,
+            display_wait: false,
+        }
+    }
+
+    // superchip is SUPER-CHIP 1.1
+    pub fn superchip() -> Quirks {
+        Quirks {
+            vf_reset: false,
+            shift_uses_vy: false,
+            index_increment: IndexIncrement::Unchanged,
+            jump_uses_vx: true,
+            clip_sprites: true,
+            memory_size: MemorySize::CLASSIC,
+            vip_timing: false,
+            display_wait: false,
+        }
+    }
+
+    // modern matches Octo and XO-CHIP, including its 64kB of memory
+    pub fn modern() -> Quirks {
+        Quirks {
+            vf_reset: false,
+            shift_uses_vy: true,
+            index_increment: IndexIncrement::XPlusOne,
+            jump_uses_vx: false,
+            clip_sprites: false,
+            memory_size: MemorySize::XO_CHIP,
+            vip_timing: false,
+            display_wait: false,
+        }
+    }
+
+    // waits_for_vblank tells whether Dxyn waits for the next frame, the VIP always does
+    pub fn waits_for_vblank(&self) -> bool {
+        self.display_wait || self.vip_timing
+    }
+
+    // from_name looks a preset up by its command line name
+    pub fn from_name(name: &str) -> Option<Quirks> {
+        match name.to_ascii_lowercase().as_str() {
+            "vip" | "cosmac" | "cosmac-vip" | "chip8" | "chip-8" => Some(Quirks::cosmac_vip()),
+            "chip48" | "chip-48" => Some(Quirks::chip48()),
+            "schip" | "superchip" | "super-chip" => Some(Quirks::superchip()),
+            "modern" | "octo" | "xochip" | "xo-chip" => Some(Quirks::modern()),
+            _ => None,
+        }
+    }
+
+    // to_bytes is how save states and movies store the quirks: the flags, the index
+    // increment and the memory size (little endian)
+    pub fn to_bytes(&self) -> [u8; 6] {
+        let flags = self.vf_reset as u8
+            | (self.shift_uses_vy as u8) << 1
+            | (self.jump_uses_vx as u8) << 2
+            | (self.clip_sprites as u8) << 3
+            | (self.vip_timing as u8) << 4
+            | (self.display_wait as u8) << 5;
+        let index_increment = match self.index_increment {
+            IndexIncrement::XPlusOne => 0,
+            IndexIncrement::X => 1,
+            IndexIncrement::Unchanged => 2,
+        };
+        let size = (self.memory_size.bytes() as u32).to_le_bytes();
+        [flags, index_increment, size[0], size[1], size[2], size[3]]
+    }
+
+    // from_bytes reads what to_bytes wrote, the error names the invalid value
+    pub fn from_bytes(bytes: [u8; 6]) -> Result<Quirks, &'static str> {
+        let [flags, index_increment, size @ ..] = bytes;
+        let index_increment = match index_increment {
+            0 => IndexIncrement::XPlusOne,
+            1 => IndexIncrement::X,
+            2 => IndexIncrement::Unchanged,
+            _ => return Err("index increment quirk"),
+        };
+        let memory_size = MemorySize::new(u32::from_le_bytes(size) as usize).ok_or("memory size")?;
+        Ok(Quirks {
+            vf_reset: flags & 0x1 != 0,
+            shift_uses_vy: flags & 0x2 != 0,
+            jump_uses_vx: flags & 0x4 != 0,
+            clip_sprites: flags & 0x8 != 0,
+            vip_timing: flags & 0x10 != 0,
+            display_wait: flags & 0x20 != 0,
+            index_increment,
+            memory_size,
+        })
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks::modern()
+    }
+}
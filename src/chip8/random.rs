@@ -0,0 +1,111 @@
+// RandomSource gives the bytes of CXNN. Its state is part of save states, so a run
+// that starts from the same seed and gets the same input always does the same thing
+pub trait RandomSource: Send {
+    // next_byte returns the next random byte, memory is the interpreter's RAM
+    fn next_byte(&mut self, memory: &[u8]) -> u8;
+
+    // name tells sources apart in save states, a state is only restored into the same kind of source
+    fn name(&self) -> &'static str;
+
+    fn state(&self) -> Vec<u8>;
+
+    // set_state returns false if state was not made by state()
+    fn set_state(&mut self, state: &[u8]) -> bool;
+}
+
+// SeededRandom is a SplitMix64 generator, any seed (even 0) works
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SeededRandom {
+    state: u64,
+}
+
+impl SeededRandom {
+    pub fn new(seed: u64) -> SeededRandom {
+        SeededRandom { state: seed }
+    }
+}
+
+impl RandomSource for SeededRandom {
+    fn next_byte(&mut self, _memory: &[u8]) -> u8 {
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        ((z ^ (z >> 31)) >> 56) as u8
+    }
+
+    fn name(&self) -> &'static str {
+        "splitmix64"
+    }
+
+    fn state(&self) -> Vec<u8> {
+        self.state.to_le_bytes().to_vec()
+    }
+
+    fn set_state(&mut self, state: &[u8]) -> bool {
+        let Ok(bytes) = state.try_into() else { return false };
+        self.state = u64::from_le_bytes(bytes);
+        true
+    }
+}
+
+// VipRandom works like the COSMAC VIP interpreter, which had no real generator: it added a byte
+// of its own code, picked by a pointer that moves on every call, to the previous result.
+// the interpreter lived below 0x200, here that is where the fonts are. The numbers are poor,
+// some games were tuned for exactly that
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct VipRandom {
+    pointer: u8,
+    value: u8,
+}
+
+impl VipRandom {
+    pub fn new(seed: u8) -> VipRandom {
+        VipRandom { pointer: seed, value: 0 }
+    }
+}
+
+impl RandomSource for VipRandom {
+    fn next_byte(&mut self, memory: &[u8]) -> u8 {
+        self.pointer = self.pointer.wrapping_add(1);
+        let code = memory.get(self.pointer as usize).copied().unwrap_or(0);
+        self.value = self.value.rotate_right(1).wrapping_add(code).wrapping_add(self.pointer);
+        self.value
+    }
+
+    fn name(&self) -> &'static str {
+        "vip"
+    }
+
+    fn state(&self) -> Vec<u8> {
+        vec![self.pointer, self.value]
+    }
+
+    fn set_state(&mut self, state: &[u8]) -> bool {
+        let &[pointer, value] = state else { return false };
+        self.pointer = pointer;
+        self.value = value;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_bytes() {
+        let bytes = |mut random: Box<dyn RandomSource>| -> Vec<u8> {
+            (0..32).map(|_| random.next_byte(&[0x12; 512])).collect()
+        };
+        assert_eq!(bytes(Box::new(SeededRandom::new(7))), bytes(Box::new(SeededRandom::new(7))));
+        assert_ne!(bytes(Box::new(SeededRandom::new(7))), bytes(Box::new(SeededRandom::new(8))));
+        assert_eq!(bytes(Box::new(VipRandom::new(3))), bytes(Box::new(VipRandom::new(3))));
+
+        let mut random = SeededRandom::new(1);
+        random.next_byte(&[]);
+        let mut copy = SeededRandom::new(0);
+        assert!(copy.set_state(&random.state()));
+        assert_eq!(copy.next_byte(&[]), random.next_byte(&[]));
+    }
+}
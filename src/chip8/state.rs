@@ -0,0 +1,313 @@
+use std::fmt;
+use crate::checksum::crc32;
+use super::display::{HIRES_HEIGHT, HIRES_WIDTH};
+use super::{Chip8, Quirks, STACK_SIZE};
+
+// a save state is MAGIC, VERSION, the machine in the order written by save_state
+// (numbers are little endian) and the CRC-32 of all the bytes before it.
+// version 1 had no random source state, version 2 no key wait and display wait state,
+// both can still be loaded
+const MAGIC: &[u8; 4] = b"C8ST";
+const VERSION: u8 = 3;
+
+// StateError is returned by load_state when the bytes are not a usable save state
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateError {
+    BadMagic, // not a save state at all
+    UnsupportedVersion(u8), // written by a newer or older emulator
+    ChecksumMismatch, // the file is damaged
+    Truncated, // ends before all of the machine was read
+    Invalid(&'static str), // a value that the emulator can't be in
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::BadMagic => write!(f, "not a chip8 save state"),
+            StateError::UnsupportedVersion(version) =>
+                write!(f, "unsupported save state version {} (expected {})", version, VERSION),
+            StateError::ChecksumMismatch => write!(f, "save state checksum mismatch"),
+            StateError::Truncated => write!(f, "save state is truncated"),
+            StateError::Invalid(what) => write!(f, "save state has invalid {}", what),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+impl Chip8 {
+    // save_state serializes the whole machine, including its quirks, into a versioned snapshot
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut out = Writer(Vec::with_capacity(self.memory.len() + HIRES_WIDTH * HIRES_HEIGHT + 128));
+        out.bytes(MAGIC);
+        out.u8(VERSION);
+
+        out.bytes(&self.quirks.to_bytes());
+        out.bytes(&self.memory);
+
+        out.bytes(&self.vx);
+        out.u8(self.dt);
+        out.u8(self.st);
+        out.u16(self.pc);
+        for address in self.stack {
+            out.u16(address);
+        }
+        out.u8(self.sp);
+        out.u16(self.i);
+
+        out.u8(self.gfx.is_hires() as u8);
+        out.u8(self.gfx.planes());
+        out.bytes(self.gfx.raw_pixels());
+
+        out.bytes(&self.rpl);
+        match self.audio_pattern {
+            Some(pattern) => {
+                out.u8(1);
+                out.bytes(&pattern);
+            },
+            None => out.u8(0),
+        }
+        out.u8(self.pitch);
+
+        let keys = self.keyboard.iter().enumerate()
+            .fold(0u16, |keys, (key, pressed)| keys | (*pressed as u16) << key);
+        out.u16(keys);
+        out.u16(self.key_presses);
+        out.u8(self.waiting_for_key as u8 | (self.vblank as u8) << 1);
+
+        let name = self.random.name();
+        let random = self.random.state();
+        out.u8(name.len() as u8);
+        out.bytes(name.as_bytes());
+        out.u8(random.len() as u8);
+        out.bytes(&random);
+
+        let checksum = crc32(&out.0);
+        out.u32(checksum);
+        out.0
+    }
+
+    // load_state replaces the machine with a snapshot made by save_state.
+    // nothing is changed if the snapshot is rejected. The random source is only restored
+    // if it is the same kind as the one the state was saved from
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        if state.len() < MAGIC.len() + 1 || &state[..MAGIC.len()] != MAGIC {
+            return Err(StateError::BadMagic);
+        }
+        let version = state[MAGIC.len()];
+        if !(1..=VERSION).contains(&version) {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+        if state.len() < MAGIC.len() + 1 + 4 {
+            return Err(StateError::Truncated);
+        }
+        let (body, checksum) = state.split_at(state.len() - 4);
+        if crc32(body) != u32::from_le_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]) {
+            return Err(StateError::ChecksumMismatch);
+        }
+
+        let mut input = Reader { data: body, position: MAGIC.len() + 1 };
+        let mut quirks = [0; 6];
+        quirks.copy_from_slice(input.bytes(6)?);
+        let quirks = Quirks::from_bytes(quirks).map_err(StateError::Invalid)?;
+        let memory = input.bytes(quirks.memory_size.bytes())?.to_vec();
+
+        let mut vx = [0; 16];
+        vx.copy_from_slice(input.bytes(16)?);
+        let dt = input.u8()?;
+        let st = input.u8()?;
+        let pc = input.u16()?;
+        let mut stack = [0; STACK_SIZE];
+        for address in stack.iter_mut() {
+            *address = input.u16()?;
+        }
+        let sp = input.u8()?;
+        if sp as usize > STACK_SIZE {
+            return Err(StateError::Invalid("stack pointer"));
+        }
+        let i = input.u16()?;
+
+        let hires = input.u8()? != 0;
+        let planes = input.u8()?;
+        let pixels = input.bytes(HIRES_WIDTH * HIRES_HEIGHT)?;
+
+        let mut rpl = [0; 16];
+        rpl.copy_from_slice(input.bytes(16)?);
+        let audio_pattern = match input.u8()? {
+            0 => None,
+            _ => {
+                let mut pattern = [0; 16];
+                pattern.copy_from_slice(input.bytes(16)?);
+                Some(pattern)
+            },
+        };
+        let pitch = input.u8()?;
+        let keys = input.u16()?;
+        // older states were taken between frames, a key wait starts over after them
+        let (key_presses, waiting_for_key, vblank) = if version >= 3 {
+            let key_presses = input.u16()?;
+            let flags = input.u8()?;
+            (key_presses, flags & 1 != 0, flags & 2 != 0)
+        } else {
+            (0, false, true)
+        };
+        let random = if version >= 2 {
+            let len = input.u8()? as usize;
+            let name = input.bytes(len)?;
+            let len = input.u8()? as usize;
+            Some((name, input.bytes(len)?))
+        } else {
+            None
+        };
+        if input.position != body.len() {
+            return Err(StateError::Invalid("length"));
+        }
+
+        self.quirks = quirks;
+        self.memory = memory;
+        self.vx = vx;
+        self.dt = dt;
+        self.st = st;
+        self.pc = pc;
+        self.stack = stack;
+        self.sp = sp;
+        self.i = i;
+        self.gfx.restore(hires, planes, pixels);
+        self.rpl = rpl;
+        self.audio_pattern = audio_pattern;
+        self.pitch = pitch;
+        self.vblank = vblank;
+        self.waiting_for_key = waiting_for_key;
+        self.key_presses = key_presses;
+        for (key, pressed) in self.keyboard.iter_mut().enumerate() {
+            *pressed = keys & (1 << key) != 0;
+        }
+        if let Some((name, random)) = random {
+            if name == self.random.name().as_bytes() {
+                self.random.set_state(random);
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        if self.position + len > self.data.len() {
+            return Err(StateError::Truncated);
+        }
+        let bytes = &self.data[self.position..self.position + len];
+        self.position += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, StateError> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::StepOutcome;
+
+    #[test]
+    fn state_round_trip() {
+        let mut chip8 = Chip8::new(Quirks::superchip());
+        // hires; ld v3, 0x42; ld i, font 0; drw v3, v3, 5; call 0x20A; 0x20A: jp 0x20A
+        chip8.load_rom(vec![0x00, 0xFF, 0x63, 0x42, 0xA0, 0x00, 0xD3, 0x35, 0x22, 0x0A, 0x12, 0x0A]).unwrap();
+        for _ in 0..5 {
+            chip8.next_instruction().unwrap();
+        }
+        chip8.key_down(7);
+        let state = chip8.save_state();
+
+        let mut restored = Chip8::default();
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.save_state(), state);
+        assert_eq!(restored.quirks(), Quirks::superchip());
+        assert_eq!(restored.get_screen(), chip8.get_screen());
+        assert_eq!((restored.pc, restored.sp, restored.vx[3]), (0x20A, 1, 0x42));
+        assert!(restored.keys()[7]);
+    }
+
+    #[test]
+    fn key_wait_continues_after_loading() {
+        // ld v0, k
+        let mut chip8 = Chip8::default();
+        chip8.load_rom(vec![0xF0, 0x0A]).unwrap();
+        assert_eq!(chip8.next_instruction(), Ok(StepOutcome::WaitingForKey));
+        chip8.key_down(9);
+        let state = chip8.save_state();
+
+        // the press seen before saving counts, the release finishes the wait
+        let mut restored = Chip8::default();
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.save_state(), state);
+        restored.key_up(9);
+        assert_eq!(restored.next_instruction(), Ok(StepOutcome::Executed));
+        assert_eq!(restored.vx[0], 9);
+    }
+
+    #[test]
+    fn damaged_state_is_rejected() {
+        let chip8 = Chip8::default();
+        let mut state = chip8.save_state();
+        let mut target = Chip8::new(Quirks::cosmac_vip());
+        state[100] ^= 0xFF;
+        assert_eq!(target.load_state(&state), Err(StateError::ChecksumMismatch));
+        assert_eq!(target.load_state(b"nope"), Err(StateError::BadMagic));
+        assert_eq!(target.quirks(), Quirks::cosmac_vip());
+    }
+
+    #[test]
+    fn random_source_is_restored() {
+        // rnd v0, 0xFF; jp 0x200
+        let rom = vec![0xC0, 0xFF, 0x12, 0x00];
+        let mut chip8 = Chip8::with_seed(Quirks::default(), 1);
+        chip8.load_rom(rom.clone()).unwrap();
+        let state = chip8.save_state();
+        let rolls = |chip8: &mut Chip8| -> Vec<u8> {
+            (0..8).map(|_| {
+                chip8.next_instruction().unwrap();
+                chip8.next_instruction().unwrap();
+                chip8.vx[0]
+            }).collect()
+        };
+        let expected = rolls(&mut chip8);
+
+        let mut restored = Chip8::with_seed(Quirks::default(), 99);
+        restored.load_state(&state).unwrap();
+        assert_eq!(rolls(&mut restored), expected);
+    }
+}
@@ -0,0 +1,52 @@
+use super::{Chip8, Instruction};
+
+// the COSMAC VIP runs its CDP1802 at 1.7609MHz, 8 clocks make a machine cycle, so a 60Hz
+// frame of 262 display lines is 3668 machine cycles. The CDP1861 display takes 8 DMA cycles
+// on each of the 128 visible lines and its interrupt routine some more, the interpreter
+// gets the rest
+pub const VIP_CYCLES_PER_FRAME: u32 = 3668;
+pub const VIP_DISPLAY_CYCLES: u32 = 128 * 8 + 46;
+pub const VIP_INTERPRETER_CYCLES: u32 = VIP_CYCLES_PER_FRAME - VIP_DISPLAY_CYCLES;
+
+// the interpreter loop fetches, decodes and dispatches every instruction before running it
+const FETCH_CYCLES: u32 = 40;
+
+impl Chip8 {
+    // vip_cycles returns how many machine cycles the VIP interpreter needs for the instruction
+    // at pc, given the registers as they are now. The costs are estimates from the structure
+    // of the original interpreter: CLS clears 256 bytes one by one, DXYN is slower on rows
+    // that aren't byte aligned, FX33 subtracts digit by digit and FX55/FX65 loop per register.
+    // instructions the VIP doesn't have cost as much as an arithmetic one
+    pub fn vip_cycles(&self) -> u32 {
+        let Some(instruction) = self.instruction_at(self.pc) else {
+            return FETCH_CYCLES;
+        };
+        let execute = match instruction {
+            Instruction::Cls => 6 * 256,
+            Instruction::Ret => 24,
+            Instruction::Jump(_) | Instruction::LoadIndex(_) => 12,
+            Instruction::Call(_) => 26,
+            Instruction::JumpOffset(_) => 22,
+            Instruction::SkipEqualByte(..) | Instruction::SkipNotEqualByte(..)
+            | Instruction::SkipEqual(..) | Instruction::SkipNotEqual(..)
+            | Instruction::SkipKey(_) | Instruction::SkipNotKey(_) | Instruction::WaitKey(_) => 18,
+            Instruction::LoadByte(..) => 6,
+            Instruction::AddByte(..) | Instruction::GetDelay(_)
+            | Instruction::SetDelay(_) | Instruction::SetSound(_) => 10,
+            Instruction::Random(..) => 36,
+            Instruction::Draw(x, _, rows) => {
+                let aligned = self.vx[x as usize].is_multiple_of(8);
+                68 + rows as u32 * if aligned { 26 } else { 45 }
+            },
+            Instruction::AddIndex(_) => 19,
+            Instruction::Font(_) => 20,
+            Instruction::Bcd(x) => {
+                let value = self.vx[x as usize] as u32;
+                84 + 10 * (value / 100 + value / 10 % 10 + value % 10)
+            },
+            Instruction::Store(x) | Instruction::Restore(x) => 4 + 8 * (x as u32 + 1),
+            _ => 44,
+        };
+        FETCH_CYCLES + execute
+    }
+}
@@ -0,0 +1,97 @@
+use std::fmt;
+use super::{Chip8Error, Instruction, StepOutcome};
+
+// Register is something an instruction can change that the trace reports
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    V(u8), // V0 to VF
+    I,
+    Delay,
+    Sound,
+    Sp,
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Register::V(x) => write!(f, "V{:X}", x),
+            Register::I => write!(f, "I"),
+            Register::Delay => write!(f, "DT"),
+            Register::Sound => write!(f, "ST"),
+            Register::Sp => write!(f, "SP"),
+        }
+    }
+}
+
+// TraceEntry is what one call of next_instruction did
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub pc: u16,
+    pub opcode: u16,
+    pub instruction: Option<Instruction>, // None for invalid opcodes
+    pub changes: Vec<(Register, u16)>, // new values of the registers that changed
+    pub outcome: Result<StepOutcome, Chip8Error>,
+}
+
+impl fmt::Display for TraceEntry {
+    // one line: pc, opcode, mnemonic and the changes
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mnemonic = self.instruction.map_or_else(|| "???".to_string(), |instruction| instruction.to_string());
+        let mut line = format!("{:04X}  {:04X}  {:<24}", self.pc, self.opcode, mnemonic);
+        for (register, value) in &self.changes {
+            match register {
+                Register::I => line += &format!(" {}={:04X}", register, value),
+                _ => line += &format!(" {}={:02X}", register, value),
+            }
+        }
+        match &self.outcome {
+            Ok(StepOutcome::Executed) => (),
+            Ok(StepOutcome::WaitingForKey) => line += " (waiting for key)",
+            Ok(StepOutcome::WaitingForVblank) => line += " (waiting for vblank)",
+            Ok(StepOutcome::Exited) => line += " (exit)",
+            Err(err) => line += &format!(" error: {}", err),
+        }
+        write!(f, "{}", line.trim_end())
+    }
+}
+
+// TraceSink receives an entry for every executed instruction, see Chip8::set_trace_sink
+pub trait TraceSink: Send {
+    fn record(&mut self, entry: &TraceEntry);
+
+    // flush is called once per frame by the frontend, so little is lost if the process is killed
+    fn flush(&mut self) {}
+}
+
+// Registers is the part of the state compared before and after an instruction
+#[derive(Clone, Copy)]
+pub(crate) struct Registers {
+    pub vx: [u8; 16],
+    pub i: u16,
+    pub dt: u8,
+    pub st: u8,
+    pub sp: u8,
+}
+
+impl Registers {
+    // changes lists what differs in after, in the order V0..VF, I, DT, ST, SP
+    pub fn changes(&self, after: &Registers) -> Vec<(Register, u16)> {
+        let mut changes: Vec<(Register, u16)> = (0..16)
+            .filter(|&x| self.vx[x] != after.vx[x])
+            .map(|x| (Register::V(x as u8), after.vx[x] as u16))
+            .collect();
+        if self.i != after.i {
+            changes.push((Register::I, after.i));
+        }
+        if self.dt != after.dt {
+            changes.push((Register::Delay, after.dt as u16));
+        }
+        if self.st != after.st {
+            changes.push((Register::Sound, after.st as u16));
+        }
+        if self.sp != after.sp {
+            changes.push((Register::Sp, after.sp as u16));
+        }
+        changes
+    }
+}
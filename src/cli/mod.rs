@@ -0,0 +1,369 @@
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use crate::audio::{AudioConfig, Waveform};
+use crate::chip8::Quirks;
+use crate::image::Format;
+use crate::palette::{parse_color, Palette};
+use crate::rewind::RewindConfig;
+use crate::scheduler::DEFAULT_MAX_CATCH_UP;
+use crate::trace::{TraceConfig, TraceFormat};
+use crate::video::VideoFormat;
+
+const SAMPLE_RATES: RangeInclusive<u32> = 8000..=192000;
+const SCALES: RangeInclusive<u32> = 1..=64; // a 4096x2048 window at most
+
+pub const USAGE: &str = "\
+usage: miko_chip8emulator [options] <rom>
+
+options:
+  --ipf <n>              instructions executed per 60Hz frame (default 10)
+  --scale <n>            size of a chip8 pixel in window, screenshot and video pixels, 1 to 64 (default 10)
+  --quirks <preset>      vip, chip48, schip or modern (default modern)
+  --fg <color>           color of lit pixels, RRGGBB or a name (default white)
+  --bg <color>           background color (default black)
+  --palette <c0,..,c3>   all four colors, the last two are XO-CHIP planes
+  --seed <n>             seed of the random numbers of CXNN, the same seed gives the same run
+  --vip-random           make CXNN work like the COSMAC VIP interpreter instead, --seed is 0 to 255 then
+  --vip-timing           give instructions the time they take on the COSMAC VIP instead of --ipf
+  --display-wait         make DXYN wait for the next frame like the COSMAC VIP, also with --vip-timing
+  --mute                 start muted, press M to toggle
+  --tone <hz>            frequency of the beep (default 440)
+  --volume <0-100>       loudness of the beep (default 25)
+  --waveform <shape>     square, triangle, sawtooth or sine (default square)
+  --sample-rate <hz>     sample rate of the sound output, 8000 to 192000 (default 44100)
+  --audio-out <file>     write the sound to a WAV file instead of playing it, also with --headless
+  --paused               start paused, press P to resume
+  --fullscreen           open a borderless fullscreen window
+  --keymap <file>        host keys of the keypad (default ~/.config/miko_chip8emulator/keymap.ini if it exists)
+  --load-state <file>    start from a save state instead of the beginning of the ROM
+  --record <file>        record the keys of every frame into a movie file
+  --play <file>          play a movie back, with the seed, quirks and speed it was recorded with
+  --max-catch-up <n>     frames run at once to catch up after a stall, older ones are skipped (default 6)
+  --rewind-seconds <n>   how far Backspace can rewind (default 30)
+  --rewind-memory <mb>   memory the rewind buffer may use (default 16)
+  --debug                read debugger commands from stdin (type help for a list)
+  --headless             run without a window
+  --frames <n>           number of frames to run in headless mode
+  --dump-screen <file>   write the screen as text to file after a headless run
+  --screenshot <file>    save the screen as .png, .pbm or .pgm after a headless run
+  --video <file>         record every frame into an animated .gif or a .y4m video, F2 toggles
+  --frame-skip <n>       keep every nth frame in videos (default 2 for .gif, 1 for .y4m)
+  --trace <file>         log every executed instruction with the registers it changed
+  --trace-format <f>     text or binary (default text)
+  --trace-range <a-b>    only log instructions between two hexadecimal addresses, can be repeated
+  --trace-max-mb <n>     start a new trace file after n megabytes, the old one becomes <file>.1
+  -h, --help             print this help";
+
+// Options is everything that can be set from the command line
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    pub rom: PathBuf,
+    pub instructions_per_frame: u32,
+    pub scale: u32,
+    pub quirks: Quirks,
+    pub palette: Palette,
+    pub seed: Option<u64>, // random if not given
+    pub vip_random: bool,
+    pub mute: bool,
+    pub audio: AudioConfig,
+    pub audio_out: Option<PathBuf>,
+    pub paused: bool,
+    pub fullscreen: bool,
+    pub keymap: Option<PathBuf>,
+    pub load_state: Option<PathBuf>,
+    pub record: Option<PathBuf>,
+    pub play: Option<PathBuf>,
+    pub max_catch_up: u32,
+    pub rewind: RewindConfig,
+    pub debug: bool,
+    pub headless: bool,
+    pub frames: Option<u64>,
+    pub dump_screen: Option<PathBuf>,
+    pub screenshot: Option<PathBuf>,
+    pub video: Option<PathBuf>,
+    pub frame_skip: Option<u32>, // the default of the video format if None
+    pub trace: Option<TraceConfig>,
+}
+
+// CliError is returned when the command line can't be understood
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CliError {
+    Help, // --help was asked for, not really an error
+    MissingValue(String), // option needs a value, but it is the last argument
+    InvalidValue { option: String, value: String },
+    UnknownOption(String),
+    MissingRom,
+    Conflict(String), // options that can't be used together
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Help => write!(f, "{}", USAGE),
+            CliError::MissingValue(option) => write!(f, "{} needs a value", option),
+            CliError::InvalidValue { option, value } =>
+                write!(f, "invalid value '{}' for {}", value, option),
+            CliError::UnknownOption(option) => write!(f, "unknown option {}", option),
+            CliError::MissingRom => write!(f, "no ROM file given"),
+            CliError::Conflict(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+impl Options {
+    // parse reads the options from the arguments, without the program name
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Options, CliError> {
+        let mut options = Options {
+            rom: PathBuf::new(),
+            instructions_per_frame: 10,
+            scale: 10,
+            quirks: Quirks::default(),
+            palette: Palette::default(),
+            seed: None,
+            vip_random: false,
+            mute: false,
+            audio: AudioConfig::default(),
+            audio_out: None,
+            paused: false,
+            fullscreen: false,
+            keymap: None,
+            load_state: None,
+            record: None,
+            play: None,
+            max_catch_up: DEFAULT_MAX_CATCH_UP,
+            rewind: RewindConfig::default(),
+            debug: false,
+            headless: false,
+            frames: None,
+            dump_screen: None,
+            screenshot: None,
+            video: None,
+            frame_skip: None,
+            trace: None,
+        };
+        let mut rom = None;
+        // applied after the loop, so --quirks doesn't undo them
+        let mut vip_timing = false;
+        let mut display_wait = false;
+        let mut trace = TraceConfig::new(PathBuf::new());
+        let mut trace_options = Vec::new(); // --trace-* given, they need --trace
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = |option: &str| args.next().ok_or_else(|| CliError::MissingValue(option.to_string()));
+            match arg.as_str() {
+                "-h" | "--help" => return Err(CliError::Help),
+                "--ipf" => options.instructions_per_frame = parse_number(&arg, value(&arg)?)?,
+                "--scale" => options.scale = parse_number(&arg, value(&arg)?)?,
+                "--quirks" => {
+                    let preset = value(&arg)?;
+                    options.quirks = Quirks::from_name(&preset)
+                        .ok_or(CliError::InvalidValue { option: arg, value: preset })?;
+                },
+                "--fg" => {
+                    let color = value(&arg)?;
+                    options.palette.colors[1] = parse_color(&color)
+                        .ok_or(CliError::InvalidValue { option: arg, value: color })?;
+                },
+                "--bg" => {
+                    let color = value(&arg)?;
+                    options.palette.colors[0] = parse_color(&color)
+                        .ok_or(CliError::InvalidValue { option: arg, value: color })?;
+                },
+                "--palette" => {
+                    let list = value(&arg)?;
+                    options.palette = Palette::parse(&list)
+                        .ok_or(CliError::InvalidValue { option: arg, value: list })?;
+                },
+                "--seed" => options.seed = Some(parse_number(&arg, value(&arg)?)?),
+                "--vip-random" => options.vip_random = true,
+                "--vip-timing" => vip_timing = true,
+                "--display-wait" => display_wait = true,
+                "--mute" => options.mute = true,
+                "--tone" => options.audio.frequency = parse_number(&arg, value(&arg)?)?,
+                "--volume" => {
+                    let volume: u8 = parse_number(&arg, value(&arg)?)?;
+                    if volume > 100 {
+                        return Err(CliError::InvalidValue { option: arg, value: volume.to_string() });
+                    }
+                    options.audio.volume = volume as f64 / 100.0;
+                },
+                "--waveform" => {
+                    let shape = value(&arg)?;
+                    options.audio.waveform = Waveform::from_name(&shape)
+                        .ok_or(CliError::InvalidValue { option: arg, value: shape })?;
+                },
+                "--sample-rate" => options.audio.sample_rate = parse_number(&arg, value(&arg)?)?,
+                "--audio-out" => options.audio_out = Some(PathBuf::from(value(&arg)?)),
+                "--paused" => options.paused = true,
+                "--fullscreen" => options.fullscreen = true,
+                "--load-state" => options.load_state = Some(PathBuf::from(value(&arg)?)),
+                "--record" => options.record = Some(PathBuf::from(value(&arg)?)),
+                "--keymap" => options.keymap = Some(PathBuf::from(value(&arg)?)),
+                "--play" => options.play = Some(PathBuf::from(value(&arg)?)),
+                "--max-catch-up" => options.max_catch_up = parse_number(&arg, value(&arg)?)?,
+                "--rewind-seconds" => options.rewind.max_frames = parse_scaled(&arg, value(&arg)?, 60)?,
+                "--rewind-memory" => options.rewind.memory_budget = parse_scaled(&arg, value(&arg)?, 1024 * 1024)?,
+                "--debug" => options.debug = true,
+                "--headless" => options.headless = true,
+                "--frames" => options.frames = Some(parse_number(&arg, value(&arg)?)?),
+                "--dump-screen" => options.dump_screen = Some(PathBuf::from(value(&arg)?)),
+                "--screenshot" => {
+                    let path = PathBuf::from(value(&arg)?);
+                    if Format::from_path(&path).is_none() {
+                        return Err(CliError::InvalidValue { option: arg, value: path.display().to_string() });
+                    }
+                    options.screenshot = Some(path);
+                },
+                "--video" => {
+                    let path = PathBuf::from(value(&arg)?);
+                    if VideoFormat::from_path(&path).is_none() {
+                        return Err(CliError::InvalidValue { option: arg, value: path.display().to_string() });
+                    }
+                    options.video = Some(path);
+                },
+                "--frame-skip" => options.frame_skip = Some(parse_number(&arg, value(&arg)?)?),
+                "--trace" => options.trace = Some(TraceConfig::new(PathBuf::from(value(&arg)?))),
+                "--trace-format" => {
+                    let format = value(&arg)?;
+                    trace.format = TraceFormat::from_name(&format)
+                        .ok_or(CliError::InvalidValue { option: arg.clone(), value: format })?;
+                    trace_options.push(arg);
+                },
+                "--trace-range" => {
+                    let range = value(&arg)?;
+                    trace.ranges.push(parse_range(&range)
+                        .ok_or(CliError::InvalidValue { option: arg.clone(), value: range })?);
+                    trace_options.push(arg);
+                },
+                "--trace-max-mb" => {
+                    trace.max_bytes = Some(parse_scaled(&arg, value(&arg)?, 1024 * 1024)?);
+                    trace_options.push(arg);
+                },
+                _ if arg.starts_with('-') && arg.len() > 1 => return Err(CliError::UnknownOption(arg)),
+                _ if rom.is_none() => rom = Some(PathBuf::from(arg)),
+                _ => return Err(CliError::Conflict(format!("more than one ROM given: {}", arg))),
+            }
+        }
+        options.rom = rom.ok_or(CliError::MissingRom)?;
+        options.quirks.vip_timing |= vip_timing;
+        options.quirks.display_wait |= display_wait;
+        match &mut options.trace {
+            Some(config) => *config = TraceConfig { path: config.path.clone(), ..trace },
+            None if !trace_options.is_empty() =>
+                return Err(CliError::Conflict(format!("{} needs --trace", trace_options[0]))),
+            None => (),
+        }
+
+        if options.instructions_per_frame == 0 {
+            return Err(CliError::InvalidValue { option: "--ipf".to_string(), value: "0".to_string() });
+        }
+        if !SAMPLE_RATES.contains(&options.audio.sample_rate) {
+            return Err(CliError::InvalidValue { option: "--sample-rate".to_string(), value: options.audio.sample_rate.to_string() });
+        }
+        if !options.audio.frequency.is_finite() || options.audio.frequency <= 0.0 {
+            return Err(CliError::InvalidValue { option: "--tone".to_string(), value: options.audio.frequency.to_string() });
+        }
+        if !SCALES.contains(&options.scale) {
+            return Err(CliError::InvalidValue { option: "--scale".to_string(), value: options.scale.to_string() });
+        }
+        if options.frame_skip == Some(0) {
+            return Err(CliError::InvalidValue { option: "--frame-skip".to_string(), value: "0".to_string() });
+        }
+        // the VIP generator only has a byte of state
+        if let (true, Some(seed @ 256..)) = (options.vip_random, options.seed) {
+            return Err(CliError::InvalidValue { option: "--seed".to_string(), value: seed.to_string() });
+        }
+        if options.headless && options.frames.is_none() && !options.debug && options.play.is_none() {
+            return Err(CliError::Conflict("--headless needs --frames, --debug or --play".to_string()));
+        }
+        // movies start at power on, from the ROM alone
+        if options.record.is_some() && options.play.is_some() {
+            return Err(CliError::Conflict("--record and --play can't be used together".to_string()));
+        }
+        if (options.record.is_some() || options.play.is_some()) && options.load_state.is_some() {
+            return Err(CliError::Conflict("movies can't start from --load-state".to_string()));
+        }
+        if options.headless && options.record.is_some() {
+            return Err(CliError::Conflict("--record needs a window".to_string()));
+        }
+        if !options.headless && (options.frames.is_some() || options.dump_screen.is_some() || options.screenshot.is_some()) {
+            return Err(CliError::Conflict("--frames, --dump-screen and --screenshot only work with --headless".to_string()));
+        }
+        Ok(options)
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(option: &str, value: String) -> Result<T, CliError> {
+    value.parse().map_err(|_| CliError::InvalidValue { option: option.to_string(), value })
+}
+
+// parse_scaled reads a number in a bigger unit, like seconds for frames, multiplied by
+// factor. values that don't fit into T are invalid
+fn parse_scaled<T: TryFrom<u64>>(option: &str, value: String, factor: u64) -> Result<T, CliError> {
+    let scaled = value.parse::<u64>().ok()
+        .and_then(|number| number.checked_mul(factor))
+        .and_then(|number| T::try_from(number).ok());
+    scaled.ok_or(CliError::InvalidValue { option: option.to_string(), value })
+}
+
+// parse_range reads a hexadecimal address range like 200-2FF, or a single address
+fn parse_range(value: &str) -> Option<RangeInclusive<u16>> {
+    let hex = |text: &str| u16::from_str_radix(text.trim().trim_start_matches("0x"), 16).ok();
+    match value.split_once('-') {
+        Some((start, end)) => Some(hex(start)?..=hex(end)?),
+        None => hex(value).map(|address| address..=address),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, CliError> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn defaults_and_values() {
+        let options = parse(&["--ipf", "20", "--quirks", "schip", "--fg", "#00ff00", "game.ch8"]).unwrap();
+        assert_eq!(options.rom, PathBuf::from("game.ch8"));
+        assert_eq!(options.instructions_per_frame, 20);
+        assert_eq!(options.scale, 10);
+        assert_eq!(options.quirks, Quirks::superchip());
+        assert_eq!(options.palette.colors[1], 0x00FF00);
+
+        let options = parse(&["rom", "--trace-range", "200-2ff", "--trace", "log", "--trace-range", "300"]).unwrap();
+        assert_eq!(options.trace.unwrap().ranges, [0x200..=0x2FF, 0x300..=0x300]);
+
+        let options = parse(&["--vip-timing", "--quirks", "vip", "rom"]).unwrap();
+        assert_eq!(options.quirks, Quirks { vip_timing: true, ..Quirks::cosmac_vip() });
+    }
+
+    #[test]
+    fn helpful_errors() {
+        assert_eq!(parse(&[]), Err(CliError::MissingRom));
+        assert_eq!(parse(&["rom", "--ipf"]), Err(CliError::MissingValue("--ipf".to_string())));
+        assert_eq!(parse(&["rom", "--quirks", "nope"]),
+                   Err(CliError::InvalidValue { option: "--quirks".to_string(), value: "nope".to_string() }));
+        assert!(matches!(parse(&["rom", "--headless"]), Err(CliError::Conflict(_))));
+        assert!(matches!(parse(&["rom", "--trace-range", "200-2ff"]), Err(CliError::Conflict(_))));
+        assert!(matches!(parse(&["rom", "--play", "a", "--record", "b"]), Err(CliError::Conflict(_))));
+        assert!(parse(&["rom", "--headless", "--play", "a"]).is_ok());
+        assert!(matches!(parse(&["rom", "--volume", "101"]), Err(CliError::InvalidValue { .. })));
+        assert!(matches!(parse(&["rom", "--headless", "--frames", "1", "--screenshot", "shot.bmp"]),
+                         Err(CliError::InvalidValue { .. })));
+        assert!(matches!(parse(&["rom", "--video", "clip.mp4"]), Err(CliError::InvalidValue { .. })));
+        assert!(matches!(parse(&["rom", "--frame-skip", "0"]), Err(CliError::InvalidValue { .. })));
+        assert!(matches!(parse(&["rom", "--rewind-seconds", "18446744073709551615"]), Err(CliError::InvalidValue { .. })));
+        assert!(matches!(parse(&["rom", "--rewind-memory", "18446744073709551615"]), Err(CliError::InvalidValue { .. })));
+        assert!(matches!(parse(&["rom", "--vip-random", "--seed", "256"]), Err(CliError::InvalidValue { .. })));
+        assert!(parse(&["rom", "--vip-random", "--seed", "255"]).is_ok());
+        assert!(matches!(parse(&["rom", "--scale", "65"]), Err(CliError::InvalidValue { .. })));
+        assert!(matches!(parse(&["rom", "--sample-rate", "4294967295"]), Err(CliError::InvalidValue { .. })));
+        assert!(matches!(parse(&["rom", "--tone", "inf"]), Err(CliError::InvalidValue { .. })));
+        assert!(matches!(parse(&["rom", "--trace-max-mb", "18446744073709551615"]), Err(CliError::InvalidValue { .. })));
+    }
+}
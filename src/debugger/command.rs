@@ -0,0 +1,177 @@
+use std::fmt::Write;
+use crate::chip8::Chip8;
+use super::{Breakpoint, Debugger};
+
+pub const HELP: &str = "\
+commands:
+  s, step                  execute one instruction
+  n, next                  step over a 2nnn call
+  finish, out              run until the current subroutine returns
+  c, continue              run until a breakpoint
+  until <addr>             run until pc reaches addr
+  b, break <addr>          break before the instruction at addr
+  break opcode <op> [mask] break before instructions with opcode & mask == op
+  break i <value>          break after I is set to value
+  watch <addr>             break after the byte at addr changes
+  d, delete <n>            remove breakpoint n
+  bl, breakpoints          list breakpoints
+  r, regs                  show registers, timers and the stack
+  x, mem <addr> [len]      show memory
+  q, quit                  leave the debugger
+  h, help                  show this help
+numbers are hexadecimal, with or without 0x";
+
+// Command is one line typed into the debugger prompt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Step,
+    StepOver,
+    StepOut,
+    Continue,
+    RunTo(u16),
+    Break(Breakpoint),
+    Delete(usize),
+    Breakpoints,
+    Registers,
+    Memory { address: u16, len: u16 },
+    Quit,
+    Help,
+}
+
+impl Command {
+    // parse reads a command, the error is a message for the user
+    pub fn parse(line: &str) -> Result<Command, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let argument = |index: usize| -> Result<u16, String> {
+            let word = words.get(index).ok_or_else(|| format!("{} needs an argument", words[0]))?;
+            parse_hex(word).ok_or_else(|| format!("not a hexadecimal number: {}", word))
+        };
+        let command = match words.first().copied() {
+            None => return Err("empty command".to_string()),
+            Some("s" | "step") => Command::Step,
+            Some("n" | "next") => Command::StepOver,
+            Some("finish" | "out") => Command::StepOut,
+            Some("c" | "continue") => Command::Continue,
+            Some("until" | "run-to") => Command::RunTo(argument(1)?),
+            Some("b" | "break") => match words.get(1).copied() {
+                Some("opcode") => {
+                    let value = argument(2)?;
+                    let mask = if words.len() > 3 { argument(3)? } else { 0xFFFF };
+                    Command::Break(Breakpoint::Opcode { value: value & mask, mask })
+                },
+                Some("i") => Command::Break(Breakpoint::Index(argument(2)?)),
+                _ => Command::Break(Breakpoint::Pc(argument(1)?)),
+            },
+            Some("watch") => Command::Break(Breakpoint::Memory(argument(1)?)),
+            Some("d" | "delete") => Command::Delete(argument(1)? as usize),
+            Some("bl" | "breakpoints") => Command::Breakpoints,
+            Some("r" | "regs") => Command::Registers,
+            Some("x" | "mem") => Command::Memory {
+                address: argument(1)?,
+                len: if words.len() > 2 { argument(2)? } else { 0x40 },
+            },
+            Some("q" | "quit") => Command::Quit,
+            Some("h" | "help") => Command::Help,
+            Some(other) => return Err(format!("unknown command {}, try help", other)),
+        };
+        Ok(command)
+    }
+}
+
+fn parse_hex(word: &str) -> Option<u16> {
+    let digits = word.strip_prefix("0x").or_else(|| word.strip_prefix("0X")).unwrap_or(word);
+    u16::from_str_radix(digits, 16).ok()
+}
+
+impl Debugger {
+    // execute applies a command and returns what should be shown to the user.
+    // commands that run the program only set the goal, the caller drives it with run()
+    pub fn execute(&mut self, chip8: &Chip8, command: Command) -> String {
+        match command {
+            Command::Step => self.step(chip8),
+            Command::StepOver => self.step_over(chip8),
+            Command::StepOut => self.step_out(chip8),
+            Command::Continue => self.resume(chip8),
+            Command::RunTo(address) => self.run_to(chip8, address),
+            Command::Break(breakpoint) => {
+                let index = self.add_breakpoint(breakpoint);
+                return format!("breakpoint {}: {}", index, breakpoint);
+            },
+            Command::Delete(index) => {
+                return if self.remove_breakpoint(index) {
+                    format!("deleted breakpoint {}", index)
+                } else {
+                    format!("no breakpoint {}", index)
+                };
+            },
+            Command::Breakpoints => {
+                if self.breakpoints().is_empty() {
+                    return "no breakpoints".to_string();
+                }
+                let mut out = String::new();
+                for (index, breakpoint) in self.breakpoints().iter().enumerate() {
+                    let _ = writeln!(out, "{}: {}", index, breakpoint);
+                }
+                out.pop();
+                return out;
+            },
+            Command::Registers => return format_registers(chip8),
+            Command::Memory { address, len } => return format_memory(chip8, address, len),
+            Command::Help => return HELP.to_string(),
+            Command::Quit => (),
+        }
+        String::new()
+    }
+}
+
+// format_registers shows pc, the next opcode and its mnemonic, I, timers, V0 to VF and the stack
+pub fn format_registers(chip8: &Chip8) -> String {
+    let mut out = String::new();
+    let opcode = chip8.opcode_at(chip8.pc())
+        .map(|opcode| format!("{:04X}", opcode))
+        .unwrap_or_else(|| "----".to_string());
+    let mnemonic = chip8.instruction_at(chip8.pc())
+        .map(|instruction| instruction.to_string())
+        .unwrap_or_else(|| "???".to_string());
+    let _ = writeln!(out, "PC {:04X}  OP {}  I {:04X}  SP {:X}  DT {:02X}  ST {:02X}",
+                     chip8.pc(), opcode, chip8.i(), chip8.sp(), chip8.delay_timer(), chip8.sound_timer());
+    let _ = writeln!(out, "   {}", mnemonic);
+    for (half, registers) in chip8.registers().chunks(8).enumerate() {
+        let line: Vec<String> = registers.iter().enumerate()
+            .map(|(i, value)| format!("V{:X} {:02X}", half * 8 + i, value))
+            .collect();
+        let _ = writeln!(out, "{}", line.join("  "));
+    }
+    let stack: Vec<String> = chip8.stack().iter().map(|address| format!("{:04X}", address)).collect();
+    let _ = write!(out, "STACK {}", if stack.is_empty() { "-".to_string() } else { stack.join(" ") });
+    out
+}
+
+// format_memory shows len bytes starting at address, 16 per line
+pub fn format_memory(chip8: &Chip8, address: u16, len: u16) -> String {
+    let memory = chip8.memory();
+    let start = (address as usize).min(memory.len());
+    let end = (start + len as usize).min(memory.len());
+    let mut out = String::new();
+    for (line, bytes) in memory[start..end].chunks(16).enumerate() {
+        let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+        let _ = writeln!(out, "{:04X}: {}", start + line * 16, hex.join(" "));
+    }
+    out.pop();
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(Command::parse("b 0x20a"), Ok(Command::Break(Breakpoint::Pc(0x20A))));
+        assert_eq!(Command::parse("break opcode D000 F000"),
+                   Ok(Command::Break(Breakpoint::Opcode { value: 0xD000, mask: 0xF000 })));
+        assert_eq!(Command::parse("x 300 10"), Ok(Command::Memory { address: 0x300, len: 0x10 }));
+        assert!(Command::parse("until").is_err());
+        assert!(Command::parse("jump").is_err());
+    }
+}
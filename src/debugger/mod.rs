@@ -0,0 +1,291 @@
+use std::fmt;
+use crate::chip8::{Chip8, Chip8Error, StepOutcome};
+use crate::machine::Machine;
+
+mod command;
+mod repl;
+
+pub use command::{format_memory, format_registers, Command};
+pub use repl::repl;
+
+// Target is what the debugger can drive: a bare Chip8 (timers are ticked by whoever owns it)
+// or a Machine (timers tick with the instructions)
+pub trait Target {
+    fn chip8(&self) -> &Chip8;
+    fn step(&mut self) -> Result<StepOutcome, Chip8Error>;
+}
+
+impl Target for Chip8 {
+    fn chip8(&self) -> &Chip8 {
+        self
+    }
+
+    fn step(&mut self) -> Result<StepOutcome, Chip8Error> {
+        self.next_instruction()
+    }
+}
+
+impl Target for Machine {
+    fn chip8(&self) -> &Chip8 {
+        Machine::chip8(self)
+    }
+
+    fn step(&mut self) -> Result<StepOutcome, Chip8Error> {
+        Machine::step(self)
+    }
+}
+
+// Breakpoint is a condition that pauses execution
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Breakpoint {
+    Pc(u16), // before the instruction at this address runs
+    Opcode { value: u16, mask: u16 }, // before an instruction with opcode & mask == value runs
+    Memory(u16), // after an instruction changed the byte at this address
+    Index(u16), // after an instruction set I to this value
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Breakpoint::Pc(address) => write!(f, "pc == {:#05X}", address),
+            Breakpoint::Opcode { value, mask: 0xFFFF } => write!(f, "opcode == {:04X}", value),
+            Breakpoint::Opcode { value, mask } => write!(f, "opcode & {:04X} == {:04X}", mask, value),
+            Breakpoint::Memory(address) => write!(f, "memory[{:#05X}] changes", address),
+            Breakpoint::Index(value) => write!(f, "I == {:#05X}", value),
+        }
+    }
+}
+
+// Stop tells why the debugger stopped running
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stop {
+    Breakpoint(usize), // index into breakpoints()
+    Done, // the step, step over, step out or run to finished
+    Exited, // the program executed 00FD
+    Crashed(Chip8Error),
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stop::Breakpoint(index) => write!(f, "hit breakpoint {}", index),
+            Stop::Done => write!(f, "stopped"),
+            Stop::Exited => write!(f, "program exited"),
+            Stop::Crashed(err) => write!(f, "crashed: {}", err),
+        }
+    }
+}
+
+// Goal is what the debugger is running towards
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Goal {
+    Step, // one instruction
+    StepOver { pc: u16, sp: u8 }, // until the call at pc - 2 returns
+    StepOut { sp: u8 }, // until the current subroutine returns
+    RunTo(u16), // until pc reaches the address
+    Continue, // until a breakpoint
+}
+
+// Debugger keeps breakpoints and runs a target until one of them or the current goal is reached
+#[derive(Debug, Default)]
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    goal: Option<Goal>,
+    resume_pc: Option<u16>, // pc and opcode breakpoints here are ignored once, so we can leave them
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger::default()
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    // add_breakpoint returns the index of the new breakpoint
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> usize {
+        self.breakpoints.push(breakpoint);
+        self.breakpoints.len() - 1
+    }
+
+    // remove_breakpoint returns false if there is no breakpoint with that index
+    pub fn remove_breakpoint(&mut self, index: usize) -> bool {
+        if index >= self.breakpoints.len() {
+            return false;
+        }
+        self.breakpoints.remove(index);
+        true
+    }
+
+    // is_running returns true while there is a goal that run() works towards
+    pub fn is_running(&self) -> bool {
+        self.goal.is_some()
+    }
+
+    pub fn pause(&mut self) {
+        self.goal = None;
+    }
+
+    // step executes one instruction
+    pub fn step(&mut self, chip8: &Chip8) {
+        self.start(chip8, Goal::Step);
+    }
+
+    // step_over runs a 2nnn call until it returns, any other instruction is a single step
+    pub fn step_over(&mut self, chip8: &Chip8) {
+        let is_call = chip8.opcode_at(chip8.pc()).is_some_and(|opcode| opcode & 0xF000 == 0x2000);
+        let goal = if is_call {
+            Goal::StepOver { pc: chip8.pc().wrapping_add(2), sp: chip8.sp() }
+        } else {
+            Goal::Step
+        };
+        self.start(chip8, goal);
+    }
+
+    // step_out runs until the 00EE of the current subroutine
+    pub fn step_out(&mut self, chip8: &Chip8) {
+        if chip8.sp() == 0 {
+            // not inside of a subroutine, nothing to return from
+            self.start(chip8, Goal::Continue);
+        } else {
+            self.start(chip8, Goal::StepOut { sp: chip8.sp() });
+        }
+    }
+
+    // run_to runs until pc reaches address
+    pub fn run_to(&mut self, chip8: &Chip8, address: u16) {
+        self.start(chip8, Goal::RunTo(address));
+    }
+
+    // resume runs until a breakpoint
+    pub fn resume(&mut self, chip8: &Chip8) {
+        self.start(chip8, Goal::Continue);
+    }
+
+    fn start(&mut self, chip8: &Chip8, goal: Goal) {
+        self.goal = Some(goal);
+        self.resume_pc = Some(chip8.pc());
+    }
+
+    // run executes up to max_steps instructions towards the goal.
+    // it returns None if the goal was not reached yet, run() can be called again to go on
+    pub fn run<T: Target>(&mut self, target: &mut T, max_steps: u64) -> Option<Stop> {
+        let goal = self.goal?;
+        for _ in 0..max_steps {
+            if let Some(index) = self.hit_before(target.chip8()) {
+                return Some(self.stop(Stop::Breakpoint(index)));
+            }
+            self.resume_pc = None;
+
+            let watched: Vec<Option<u8>> = self.breakpoints.iter()
+                .map(|breakpoint| match *breakpoint {
+                    Breakpoint::Memory(address) => target.chip8().memory().get(address as usize).copied(),
+                    _ => None,
+                })
+                .collect();
+            let index_before = target.chip8().i();
+
+            match target.step() {
+                Ok(StepOutcome::Exited) => return Some(self.stop(Stop::Exited)),
+                Ok(_) => (),
+                Err(err) => return Some(self.stop(Stop::Crashed(err))),
+            }
+
+            let chip8 = target.chip8();
+            if let Some(index) = self.hit_after(chip8, &watched, index_before) {
+                return Some(self.stop(Stop::Breakpoint(index)));
+            }
+            let done = match goal {
+                Goal::Step => true,
+                Goal::StepOver { pc, sp } => chip8.sp() < sp || (chip8.sp() == sp && chip8.pc() == pc),
+                Goal::StepOut { sp } => chip8.sp() < sp,
+                Goal::RunTo(address) => chip8.pc() == address,
+                Goal::Continue => false,
+            };
+            if done {
+                return Some(self.stop(Stop::Done));
+            }
+        }
+        None
+    }
+
+    fn stop(&mut self, stop: Stop) -> Stop {
+        self.goal = None;
+        self.resume_pc = None;
+        stop
+    }
+
+    // hit_before checks the breakpoints on the instruction that is about to run
+    fn hit_before(&self, chip8: &Chip8) -> Option<usize> {
+        let pc = chip8.pc();
+        if self.resume_pc == Some(pc) {
+            return None;
+        }
+        let opcode = chip8.opcode_at(pc);
+        self.breakpoints.iter().position(|breakpoint| match *breakpoint {
+            Breakpoint::Pc(address) => address == pc,
+            Breakpoint::Opcode { value, mask } => opcode.is_some_and(|opcode| opcode & mask == value),
+            _ => false,
+        })
+    }
+
+    // hit_after checks the breakpoints on what the instruction that just ran changed
+    fn hit_after(&self, chip8: &Chip8, watched: &[Option<u8>], index_before: u16) -> Option<usize> {
+        self.breakpoints.iter().enumerate().position(|(index, breakpoint)| match *breakpoint {
+            Breakpoint::Memory(address) =>
+                chip8.memory().get(address as usize).copied() != watched[index],
+            Breakpoint::Index(value) => chip8.i() == value && index_before != value,
+            _ => false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 200: call 208; 202: ld v0, 1; 204: ld i, 0x300; 206: jp 206
+    // 208: ld v1, 2; 20A: ret
+    const ROM: [u8; 12] = [0x22, 0x08, 0x60, 0x01, 0xA3, 0x00, 0x12, 0x06, 0x61, 0x02, 0x00, 0xEE];
+
+    fn chip8() -> Chip8 {
+        let mut chip8 = Chip8::default();
+        chip8.load_rom(ROM.to_vec()).unwrap();
+        chip8
+    }
+
+    #[test]
+    fn step_over_and_out() {
+        let mut chip8 = chip8();
+        let mut debugger = Debugger::new();
+        debugger.step_over(&chip8);
+        assert_eq!(debugger.run(&mut chip8, 100), Some(Stop::Done));
+        assert_eq!((chip8.pc(), chip8.registers()[1]), (0x202, 2));
+
+        let mut chip8 = self::chip8();
+        debugger.step(&chip8);
+        debugger.run(&mut chip8, 100);
+        assert_eq!(chip8.pc(), 0x208);
+        debugger.step_out(&chip8);
+        assert_eq!(debugger.run(&mut chip8, 100), Some(Stop::Done));
+        assert_eq!(chip8.pc(), 0x202);
+    }
+
+    #[test]
+    fn breakpoints_stop_and_can_be_left() {
+        let mut chip8 = chip8();
+        let mut debugger = Debugger::new();
+        debugger.add_breakpoint(Breakpoint::Pc(0x20A));
+        debugger.add_breakpoint(Breakpoint::Index(0x300));
+        debugger.resume(&chip8);
+        assert_eq!(debugger.run(&mut chip8, 100), Some(Stop::Breakpoint(0)));
+        assert_eq!(chip8.pc(), 0x20A);
+        debugger.resume(&chip8);
+        assert_eq!(debugger.run(&mut chip8, 100), Some(Stop::Breakpoint(1)));
+        assert_eq!(chip8.pc(), 0x206);
+        debugger.resume(&chip8);
+        assert_eq!(debugger.run(&mut chip8, 100), None);
+        assert!(debugger.is_running());
+    }
+}
@@ -0,0 +1,72 @@
+use std::io::{self, BufRead, Write};
+use super::{format_registers, Command, Debugger, Stop, Target};
+
+// RUN_LIMIT is how many instructions continue, next, finish and until may run before giving
+// control back to the prompt, so a program that never hits a breakpoint can't hang it
+const RUN_LIMIT: u64 = 10_000_000;
+
+// repl reads debugger commands from input until quit or end of input and writes the answers to output
+pub fn repl<T: Target, R: BufRead, W: Write>(target: &mut T, debugger: &mut Debugger,
+                                              input: R, mut output: W) -> io::Result<()> {
+    writeln!(output, "{}", format_registers(target.chip8()))?;
+    let mut lines = input.lines();
+    loop {
+        write!(output, "(c8db) ")?;
+        output.flush()?;
+        let Some(line) = lines.next() else {
+            writeln!(output)?;
+            return Ok(());
+        };
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let command = match Command::parse(&line) {
+            Ok(command) => command,
+            Err(message) => {
+                writeln!(output, "{}", message)?;
+                continue;
+            },
+        };
+        if command == Command::Quit {
+            return Ok(());
+        }
+
+        let answer = debugger.execute(target.chip8(), command);
+        if !answer.is_empty() {
+            writeln!(output, "{}", answer)?;
+        }
+        if debugger.is_running() {
+            match debugger.run(target, RUN_LIMIT) {
+                Some(Stop::Done) => (),
+                Some(stop) => writeln!(output, "{}", stop)?,
+                None => {
+                    debugger.pause();
+                    writeln!(output, "paused after {} instructions without reaching the goal", RUN_LIMIT)?;
+                },
+            }
+            writeln!(output, "{}", format_registers(target.chip8()))?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::Chip8;
+
+    #[test]
+    fn session() {
+        let mut chip8 = Chip8::default();
+        // ld v0, 5; ld v1, 6; jp 0x204
+        chip8.load_rom(vec![0x60, 0x05, 0x61, 0x06, 0x12, 0x04]).unwrap();
+        let mut debugger = Debugger::new();
+        let mut output = Vec::new();
+        repl(&mut chip8, &mut debugger, "b 204\nc\nbl\nq\n".as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("breakpoint 0: pc == 0x204"));
+        assert!(output.contains("hit breakpoint 0"));
+        assert!(output.contains("V0 05  V1 06"));
+        assert_eq!(chip8.pc(), 0x204);
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use crate::chip8::Instruction;
+
+pub const ROM_ADDRESS: u16 = 0x200;
+const BYTES_PER_LINE: usize = 4;
+
+// Byte tells what a byte of the ROM was found to be
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Byte {
+    Data, // never reached by the flow analysis
+    Code, // first byte of an instruction
+    Operand, // any other byte of an instruction
+}
+
+// Disassembly is a ROM split into code and data by following the control flow from its start
+pub struct Disassembly<'a> {
+    rom: &'a [u8],
+    origin: u16,
+    bytes: Vec<Byte>,
+    code_labels: BTreeSet<u16>, // targets of jumps and calls
+    data_labels: BTreeSet<u16>, // targets of I loads
+}
+
+impl<'a> Disassembly<'a> {
+    // new analyses rom loaded at origin, execution starts at origin
+    pub fn new(rom: &'a [u8], origin: u16) -> Disassembly<'a> {
+        let mut disassembly = Disassembly {
+            rom,
+            origin,
+            bytes: vec![Byte::Data; rom.len()],
+            code_labels: BTreeSet::new(),
+            data_labels: BTreeSet::new(),
+        };
+        disassembly.trace(origin);
+        disassembly
+    }
+
+    fn byte(&self, address: u16) -> Option<Byte> {
+        let offset = address.checked_sub(self.origin)? as usize;
+        self.bytes.get(offset).copied()
+    }
+
+    fn instruction(&self, address: u16) -> Option<Instruction> {
+        let offset = address.checked_sub(self.origin)? as usize;
+        Instruction::decode_at(self.rom, offset)
+    }
+
+    // trace marks every instruction reachable from start as code
+    fn trace(&mut self, start: u16) {
+        let mut pending = vec![start];
+        while let Some(mut address) = pending.pop() {
+            loop {
+                if self.byte(address) != Some(Byte::Data) {
+                    // already traced, partly inside another instruction or outside of the ROM
+                    break;
+                }
+                let Some(instruction) = self.instruction(address) else { break };
+                let offset = (address - self.origin) as usize;
+                if self.bytes[offset + 1..offset + instruction.size()].iter().any(|&byte| byte != Byte::Data) {
+                    break;
+                }
+                self.bytes[offset] = Byte::Code;
+                for byte in &mut self.bytes[offset + 1..offset + instruction.size()] {
+                    *byte = Byte::Operand;
+                }
+                let next = address.wrapping_add(instruction.size() as u16);
+
+                match instruction {
+                    Instruction::Jump(target) => {
+                        self.code_labels.insert(target);
+                        pending.push(target);
+                        break;
+                    },
+                    Instruction::Call(target) => {
+                        self.code_labels.insert(target);
+                        pending.push(target);
+                    },
+                    // the offset is only known at runtime, the target is usually a jump table
+                    Instruction::JumpOffset(target) => {
+                        self.code_labels.insert(target);
+                        pending.push(target);
+                        break;
+                    },
+                    Instruction::Ret | Instruction::Exit => break,
+                    Instruction::LoadIndex(target) | Instruction::LongIndex(target) => {
+                        self.data_labels.insert(target);
+                    },
+                    // skips go over the next instruction, which may be a 4 byte F000
+                    Instruction::SkipEqualByte(..) | Instruction::SkipNotEqualByte(..)
+                    | Instruction::SkipEqual(..) | Instruction::SkipNotEqual(..)
+                    | Instruction::SkipKey(_) | Instruction::SkipNotKey(_) => {
+                        let skipped = self.instruction(next).map_or(2, |instruction| instruction.size());
+                        pending.push(next.wrapping_add(skipped as u16));
+                    },
+                    _ => (),
+                }
+                address = next;
+            }
+        }
+    }
+
+    // label returns the name of the label at address, if there is one
+    pub fn label(&self, address: u16) -> Option<String> {
+        // a label in the middle of an instruction can't be written, the address stays a number
+        let byte = self.byte(address)?;
+        if self.code_labels.contains(&address) && byte == Byte::Code {
+            Some(format!("L{:04X}", address))
+        } else if self.data_labels.contains(&address) && byte != Byte::Operand {
+            Some(format!("D{:04X}", address))
+        } else {
+            None
+        }
+    }
+
+    // format_instruction writes an instruction with its target address replaced by a label
+    fn format_instruction(&self, instruction: Instruction) -> String {
+        let text = instruction.to_string();
+        let (target, literal) = match instruction {
+            Instruction::Jump(target) | Instruction::Call(target)
+            | Instruction::JumpOffset(target) | Instruction::LoadIndex(target) => (target, format!("{:#05X}", target)),
+            Instruction::LongIndex(target) => (target, format!("{:#06X}", target)),
+            _ => return text,
+        };
+        match self.label(target) {
+            Some(label) => text.replace(&literal, &label),
+            None => text,
+        }
+    }
+}
+
+impl std::fmt::Display for Disassembly<'_> {
+    // the listing can be fed back to the assembler, addresses and opcodes are in comments
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut offset = 0;
+        while offset < self.rom.len() {
+            let address = self.origin.wrapping_add(offset as u16);
+            if let Some(label) = self.label(address) {
+                writeln!(f, "{}:", label)?;
+            }
+            if self.bytes[offset] == Byte::Code {
+                let instruction = self.instruction(address).expect("traced instructions decode");
+                let hex: String = self.rom[offset..offset + instruction.size()].iter()
+                    .map(|byte| format!("{:02X}", byte))
+                    .collect();
+                let text = self.format_instruction(instruction);
+                writeln!(f, "    {:<28}; {:04X}  {}", text, address, hex)?;
+                offset += instruction.size();
+                continue;
+            }
+
+            // data runs until the next label or instruction
+            let mut line = String::new();
+            let start = offset;
+            while offset < self.rom.len() && offset - start < BYTES_PER_LINE && self.bytes[offset] != Byte::Code
+                && (offset == start || self.label(self.origin.wrapping_add(offset as u16)).is_none()) {
+                if !line.is_empty() {
+                    line.push_str(", ");
+                }
+                let _ = write!(line, "{:#04X}", self.rom[offset]);
+                offset += 1;
+            }
+            writeln!(f, "    {:<28}; {:04X}", format!("byte {}", line), address)?;
+        }
+        Ok(())
+    }
+}
+
+// disassemble returns the listing of a ROM loaded at 0x200
+pub fn disassemble(rom: &[u8]) -> String {
+    Disassembly::new(rom, ROM_ADDRESS).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follows_flow_and_marks_data() {
+        // 200: ld i, 0x20A; 202: call 0x208; 204: jp 0x204; 206: data; 208: ret; 20A: data
+        let rom = [0xA2, 0x0A, 0x22, 0x08, 0x12, 0x04, 0xAB, 0xCD, 0x00, 0xEE, 0xF0, 0x90];
+        let listing = disassemble(&rom);
+        let lines: Vec<&str> = listing.lines().map(|line| line.split(';').next().unwrap().trim_end()).collect();
+        assert_eq!(lines, [
+            "    LD I, D020A",
+            "    CALL L0208",
+            "L0204:",
+            "    JP L0204",
+            "    byte 0xAB, 0xCD",
+            "L0208:",
+            "    RET",
+            "D020A:",
+            "    byte 0xF0, 0x90",
+        ]);
+    }
+}
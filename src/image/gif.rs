@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+use super::Image;
+
+const MIN_CODE_SIZE: u8 = 2; // the 4 pixel values
+const MAX_CODES: u16 = 4096; // 12-bit codes
+
+// header starts an animated GIF of width x height pixels colored by the palette of image,
+// that loops forever
+pub(crate) fn header(image: &Image) -> Vec<u8> {
+    let mut out = b"GIF89a".to_vec();
+    out.extend((image.width as u16).to_le_bytes());
+    out.extend((image.height as u16).to_le_bytes());
+    out.push(0x91); // global color table of 4 colors, 2 bits per color channel
+    out.push(0); // background color
+    out.push(0); // square pixels
+    for pixel in 0..4 {
+        out.extend(image.palette.rgb(pixel));
+    }
+    out.extend(b"\x21\xFF\x0BNETSCAPE2.0\x03\x01\x00\x00\x00"); // loop forever
+    out
+}
+
+// frame is one image of the animation, shown for delay hundredths of a second
+pub(crate) fn frame(image: &Image, delay: u16) -> Vec<u8> {
+    let mut out = vec![0x21, 0xF9, 0x04, 0x00];
+    out.extend(delay.to_le_bytes());
+    out.extend([0x00, 0x00]);
+
+    out.push(0x2C);
+    out.extend([0, 0, 0, 0]); // at the top left
+    out.extend((image.width as u16).to_le_bytes());
+    out.extend((image.height as u16).to_le_bytes());
+    out.push(0); // no local color table, not interlaced
+
+    out.push(MIN_CODE_SIZE);
+    for block in lzw(&image.pixels).chunks(255) {
+        out.push(block.len() as u8);
+        out.extend(block);
+    }
+    out.push(0);
+    out
+}
+
+pub(crate) const TRAILER: u8 = 0x3B;
+
+// BitWriter packs codes of varying size, least significant bit first
+struct BitWriter {
+    out: Vec<u8>,
+    bits: u32,
+    len: u8,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, size: u8) {
+        self.bits |= (code as u32) << self.len;
+        self.len += size;
+        while self.len >= 8 {
+            self.out.push(self.bits as u8);
+            self.bits >>= 8;
+            self.len -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.len > 0 {
+            self.out.push(self.bits as u8);
+        }
+        self.out
+    }
+}
+
+// lzw compresses pixel values (0 to 3) the way GIF does: the codes start one bit wider than
+// the pixels and grow up to 12 bits, the table starts over with a clear code once it is full
+fn lzw(pixels: &[u8]) -> Vec<u8> {
+    let clear = 1u16 << MIN_CODE_SIZE;
+    let end = clear + 1;
+    let mut writer = BitWriter { out: Vec::new(), bits: 0, len: 0 };
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut next_code = end + 1;
+    let mut size = MIN_CODE_SIZE + 1;
+    writer.write(clear, size);
+
+    let Some((&first, rest)) = pixels.split_first() else {
+        writer.write(end, size);
+        return writer.finish();
+    };
+    let mut prefix = first as u16;
+    for &pixel in rest {
+        if let Some(&code) = table.get(&(prefix, pixel)) {
+            prefix = code;
+            continue;
+        }
+        writer.write(prefix, size);
+        if next_code < MAX_CODES {
+            table.insert((prefix, pixel), next_code);
+            next_code += 1;
+            // the decoder adds this code one step later, it widens its codes then
+            if next_code == (1 << size) + 1 && size < 12 {
+                size += 1;
+            }
+        } else {
+            writer.write(clear, size);
+            table.clear();
+            next_code = end + 1;
+            size = MIN_CODE_SIZE + 1;
+        }
+        prefix = pixel as u16;
+    }
+    writer.write(prefix, size);
+    writer.write(end, size);
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // unlzw is a plain GIF decoder of the codes
+    fn unlzw(data: &[u8]) -> Vec<u8> {
+        let clear = 1u16 << MIN_CODE_SIZE;
+        let end = clear + 1;
+        let mut out = Vec::new();
+        let mut table: Vec<Vec<u8>> = Vec::new();
+        let mut size = MIN_CODE_SIZE + 1;
+        let mut previous: Option<Vec<u8>> = None;
+        let (mut bits, mut len, mut bytes) = (0u32, 0u8, data.iter());
+        loop {
+            while len < size {
+                bits |= (*bytes.next().unwrap() as u32) << len;
+                len += 8;
+            }
+            let code = (bits & ((1 << size) - 1)) as u16;
+            bits >>= size;
+            len -= size;
+            if code == clear {
+                table = (0..clear).map(|value| vec![value as u8]).collect();
+                table.push(Vec::new());
+                table.push(Vec::new());
+                size = MIN_CODE_SIZE + 1;
+                previous = None;
+                continue;
+            }
+            if code == end {
+                return out;
+            }
+            let entry = match (table.get(code as usize), &previous) {
+                (Some(entry), _) => entry.clone(),
+                (None, Some(previous)) => [previous.clone(), vec![previous[0]]].concat(),
+                (None, None) => panic!("bad code"),
+            };
+            out.extend(&entry);
+            if let Some(previous) = previous {
+                if table.len() < MAX_CODES as usize {
+                    table.push([previous, vec![entry[0]]].concat());
+                    if table.len() == 1 << size && size < 12 {
+                        size += 1;
+                    }
+                }
+            }
+            previous = Some(entry);
+        }
+    }
+
+    #[test]
+    fn lzw_round_trip() {
+        let mut state = 1u32;
+        let noise: Vec<u8> = (0..100_000).map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (state >> 16) as u8 & 3
+        }).collect();
+        for pixels in [vec![], vec![2], vec![1; 10_000], noise] {
+            assert_eq!(unlzw(&lzw(&pixels)), pixels);
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use std::io;
+use std::path::Path;
+use crate::chip8::Screen;
+use crate::palette::Palette;
+
+pub(crate) mod gif;
+mod png;
+pub(crate) mod y4m;
+
+// Format is a file format an Image can be written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Png, // with the palette colors
+    Pbm, // black and white, pixels whose color is dark are black
+    Pgm, // the brightness of the palette colors
+}
+
+impl Format {
+    // from_path picks the format by the extension of path
+    pub fn from_path(path: &Path) -> Option<Format> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "png" => Some(Format::Png),
+            "pbm" => Some(Format::Pbm),
+            "pgm" => Some(Format::Pgm),
+            _ => None,
+        }
+    }
+}
+
+// Image is a screen scaled up to whole pixels: one pixel value (0 to 3) per image pixel,
+// and the palette that colors them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+    pub palette: Palette,
+}
+
+impl Image {
+    // from_screen makes every chip8 pixel scale x scale image pixels, scale must be at least 1
+    pub fn from_screen(screen: &Screen, palette: &Palette, scale: usize) -> Image {
+        let scale = scale.max(1);
+        Image::from_screen_sized(screen, palette, screen.width * scale, screen.height * scale)
+    }
+
+    // from_screen_sized stretches the screen to width x height pixels, the nearest chip8 pixel
+    // wins. Videos use it to keep their size when a program switches resolution
+    pub fn from_screen_sized(screen: &Screen, palette: &Palette, width: usize, height: usize) -> Image {
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            let row = y * screen.height / height;
+            pixels.extend((0..width).map(|x| screen.pixel(x * screen.width / width, row) & 0x3));
+        }
+        Image { width, height, pixels, palette: *palette }
+    }
+
+    // gray returns the brightness of the color of a pixel value
+    fn gray(&self, pixel: u8) -> u8 {
+        let [r, g, b] = self.palette.rgb(pixel);
+        ((r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000) as u8
+    }
+
+    pub fn encode(&self, format: Format) -> Vec<u8> {
+        match format {
+            Format::Png => png::encode(self),
+            Format::Pbm => {
+                let mut out = format!("P4\n{} {}\n", self.width, self.height).into_bytes();
+                for row in self.pixels.chunks(self.width) {
+                    for byte in row.chunks(8) {
+                        // 1 is black in PBM
+                        let bits = byte.iter().enumerate()
+                            .filter(|(_, pixel)| self.gray(**pixel) < 0x80)
+                            .fold(0u8, |bits, (bit, _)| bits | 0x80 >> bit);
+                        out.push(bits);
+                    }
+                }
+                out
+            },
+            Format::Pgm => {
+                let mut out = format!("P5\n{} {}\n255\n", self.width, self.height).into_bytes();
+                out.extend(self.pixels.iter().map(|pixel| self.gray(*pixel)));
+                out
+            },
+        }
+    }
+
+    // save writes the image in the format given by the extension of path
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let format = Format::from_path(path).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "unknown image format, use .png, .pbm or .pgm")
+        })?;
+        std::fs::write(path, self.encode(format))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scales_and_writes_netpbm() {
+        let pixels = [0, 1, 2, 3];
+        let screen = Screen { width: 2, height: 2, pixels: &pixels };
+        let image = Image::from_screen(&screen, &Palette::default(), 2);
+        assert_eq!(image.pixels, [0, 0, 1, 1, 0, 0, 1, 1, 2, 2, 3, 3, 2, 2, 3, 3]);
+
+        assert_eq!(image.encode(Format::Pbm), b"P4\n4 4\n\xC0\xC0\x30\x30");
+        let pgm = image.encode(Format::Pgm);
+        assert_eq!(&pgm[..11], b"P5\n4 4\n255\n");
+        assert_eq!(&pgm[11..15], &[0, 0, 255, 255]);
+        assert_eq!(Format::from_path(Path::new("shot.PNG")), Some(Format::Png));
+    }
+}
@@ -0,0 +1,104 @@
+use crate::checksum::{adler32, crc32_update};
+use super::Image;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+const MAX_STORED: usize = 0xFFFF; // bytes in a stored deflate block
+
+// encode writes image as an 8-bit paletted PNG. The pixels are not compressed: the zlib
+// stream is made of stored blocks, chip8 screens are small and this needs no deflate
+pub(super) fn encode(image: &Image) -> Vec<u8> {
+    let mut out = SIGNATURE.to_vec();
+
+    let mut header = Vec::with_capacity(13);
+    header.extend((image.width as u32).to_be_bytes());
+    header.extend((image.height as u32).to_be_bytes());
+    header.extend([8, 3, 0, 0, 0]); // bit depth, paletted, deflate, no filter, no interlace
+    chunk(&mut out, b"IHDR", &header);
+
+    let palette: Vec<u8> = (0..4).flat_map(|pixel| image.palette.rgb(pixel)).collect();
+    chunk(&mut out, b"PLTE", &palette);
+
+    // every row starts with its filter type, 0 is none
+    let mut raw = Vec::with_capacity((image.width + 1) * image.height);
+    for row in image.pixels.chunks(image.width) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+    chunk(&mut out, b"IDAT", &zlib_stored(&raw));
+
+    chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+// chunk appends a PNG chunk: length, type, data and the CRC-32 of type and data
+fn chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend((data.len() as u32).to_be_bytes());
+    out.extend(kind);
+    out.extend(data);
+    out.extend(crc32_update(crc32_update(0, kind), data).to_be_bytes());
+}
+
+// zlib_stored wraps data in a zlib stream of uncompressed deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01]; // deflate with a 32kB window, no dictionary
+    let mut blocks: Vec<&[u8]> = data.chunks(MAX_STORED).collect();
+    if blocks.is_empty() {
+        blocks.push(&[]);
+    }
+    for (n, block) in blocks.iter().enumerate() {
+        out.push((n + 1 == blocks.len()) as u8); // last block flag, type 00 is stored
+        out.extend((block.len() as u16).to_le_bytes());
+        out.extend((!(block.len() as u16)).to_le_bytes());
+        out.extend(*block);
+    }
+    out.extend(adler32(data).to_be_bytes());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checksum::crc32;
+    use crate::palette::Palette;
+
+    #[test]
+    fn writes_valid_chunks() {
+        let image = Image { width: 300, height: 300, pixels: vec![1; 90000], palette: Palette::default() };
+        let png = encode(&image);
+        assert_eq!(&png[..8], &SIGNATURE);
+
+        // walk the chunks, checking their CRCs and collecting the zlib stream
+        let mut rest = &png[8..];
+        let mut kinds = Vec::new();
+        let mut zlib = Vec::new();
+        while !rest.is_empty() {
+            let len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let (kind, data) = (&rest[4..8], &rest[8..8 + len]);
+            let crc = u32::from_be_bytes(rest[8 + len..12 + len].try_into().unwrap());
+            assert_eq!(crc, crc32(&rest[4..8 + len]));
+            if kind == b"IDAT" {
+                zlib.extend_from_slice(data);
+            }
+            kinds.push(String::from_utf8(kind.to_vec()).unwrap());
+            rest = &rest[12 + len..];
+        }
+        assert_eq!(kinds, ["IHDR", "PLTE", "IDAT", "IEND"]);
+
+        // the stored blocks hold the rows, each behind a filter byte
+        let mut block = &zlib[2..];
+        let mut raw = Vec::new();
+        loop {
+            let last = block[0] & 1 == 1;
+            let len = u16::from_le_bytes([block[1], block[2]]) as usize;
+            assert_eq!(!len as u16, u16::from_le_bytes([block[3], block[4]]));
+            raw.extend_from_slice(&block[5..5 + len]);
+            block = &block[5 + len..];
+            if last {
+                break;
+            }
+        }
+        assert_eq!(raw.len(), 301 * 300);
+        assert!(raw.chunks(301).all(|row| row[0] == 0 && row[1..].iter().all(|&pixel| pixel == 1)));
+        assert_eq!(block, adler32(&raw).to_be_bytes());
+    }
+}
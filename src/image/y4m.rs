@@ -0,0 +1,25 @@
+use super::Image;
+
+// header starts a YUV4MPEG2 stream (the raw video format of ffmpeg and mjpegtools) of
+// progressive 4:4:4 frames at rate frames per second, rate is a fraction
+pub(crate) fn header(image: &Image, rate: (u32, u32)) -> Vec<u8> {
+    format!("YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C444\n", image.width, image.height, rate.0, rate.1).into_bytes()
+}
+
+// frame is one image as the Y, U and V planes, in the studio range of BT.601
+pub(crate) fn frame(image: &Image) -> Vec<u8> {
+    let colors = [0, 1, 2, 3].map(|pixel| yuv(image.palette.rgb(pixel)));
+    let mut out = b"FRAME\n".to_vec();
+    for plane in [0, 1, 2].map(|plane| colors.map(|color| color[plane])) {
+        out.extend(image.pixels.iter().map(|pixel| plane[*pixel as usize]));
+    }
+    out
+}
+
+fn yuv([r, g, b]: [u8; 3]) -> [u8; 3] {
+    let (r, g, b) = (r as i32, g as i32, b as i32);
+    let y = ((66 * r + 129 * g + 25 * b + 128) >> 8) + 16;
+    let u = ((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128;
+    let v = ((112 * r - 94 * g - 18 * b + 128) >> 8) + 128;
+    [y as u8, u as u8, v as u8]
+}
@@ -0,0 +1,198 @@
+use std::fmt;
+use speedy2d::window::VirtualKeyCode;
+
+// a keymap file is INI style: a [keys] section binds chip8 keys to host keys, and
+// [rom <crc32>] sections do the same for the ROM with that CRC-32 only, on top of [keys].
+// every line is a hex key, '=' and a comma separated list of host keys named like the
+// VirtualKeyCode variants (Key1, A, Up, Numpad5, ...), an empty list unbinds the key.
+// # and ; start comments.
+//
+//     [keys]
+//     5 = W, Up
+//     [rom 0x1A2B3C4D]
+//     4 = Left
+//
+// a host key presses one chip8 key, binding it again moves it to the new key
+
+pub const KEYPAD_KEYS: usize = 16;
+
+// Keymap tells which host keys press each chip8 key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keymap {
+    keys: [Vec<VirtualKeyCode>; KEYPAD_KEYS],
+}
+
+// KeymapError is a line of a keymap file that can't be understood
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeymapError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for KeymapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for KeymapError {}
+
+enum Section {
+    Keys,
+    ThisRom,
+    OtherRom,
+}
+
+impl Default for Keymap {
+    // the keypad is the 4x4 block under 1234 of a QWERTY keyboard
+    fn default() -> Self {
+        use VirtualKeyCode::*;
+        let mut keymap = Keymap { keys: Default::default() };
+        let layout = [
+            (0x1, Key1), (0x2, Key2), (0x3, Key3), (0xC, Key4),
+            (0x4, Q), (0x5, W), (0x6, E), (0xD, R),
+            (0x7, A), (0x8, S), (0x9, D), (0xE, F),
+            (0xA, Z), (0x0, X), (0xB, C), (0xF, V),
+        ];
+        for (key, host_key) in layout {
+            keymap.keys[key].push(host_key);
+        }
+        keymap
+    }
+}
+
+impl Keymap {
+    // load reads a keymap file: the default keymap changed by [keys], then by the
+    // [rom] sections of the ROM whose CRC-32 is rom_checksum
+    pub fn load(text: &str, rom_checksum: u32) -> Result<Keymap, KeymapError> {
+        let mut keymap = Keymap::default();
+        let mut overrides = Vec::new(); // bindings of the ROM, applied after all of [keys]
+        let mut section = None;
+        for (number, line) in text.lines().enumerate() {
+            let error = |message: String| KeymapError { line: number + 1, message };
+            let line = line.split(['#', ';']).next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+                let mut words = name.split_whitespace();
+                section = match (words.next(), words.next(), words.next()) {
+                    (Some("keys"), None, _) => Some(Section::Keys),
+                    (Some("rom"), Some(checksum), None) => {
+                        let hex = checksum.trim_start_matches("0x").trim_start_matches("0X");
+                        let checksum = u32::from_str_radix(hex, 16)
+                            .map_err(|_| error(format!("invalid ROM checksum '{}'", checksum)))?;
+                        Some(if checksum == rom_checksum { Section::ThisRom } else { Section::OtherRom })
+                    },
+                    _ => return Err(error(format!("unknown section [{}]", name))),
+                };
+                continue;
+            }
+            let Some((key, host_keys)) = line.split_once('=') else {
+                return Err(error(format!("expected <key> = <host keys>, found '{}'", line)));
+            };
+            let key = key.trim();
+            let key = u8::from_str_radix(key, 16).ok().filter(|key| (*key as usize) < KEYPAD_KEYS)
+                .ok_or_else(|| error(format!("'{}' is not a chip8 key, use 0 to F", key)))?;
+            let host_keys = host_keys.split(',').map(str::trim).filter(|name| !name.is_empty())
+                .map(|name| host_key(name).ok_or_else(|| error(format!("unknown host key '{}'", name))))
+                .collect::<Result<Vec<_>, _>>()?;
+            match section {
+                None => return Err(error("binding outside of a section".to_string())),
+                Some(Section::Keys) => keymap.bind(key as usize, &host_keys),
+                Some(Section::ThisRom) => overrides.push((key as usize, host_keys)),
+                Some(Section::OtherRom) => (),
+            }
+        }
+        for (key, host_keys) in overrides {
+            keymap.bind(key, &host_keys);
+        }
+        Ok(keymap)
+    }
+
+    // bind makes host_keys the only keys that press key, taking them from other chip8 keys
+    pub fn bind(&mut self, key: usize, host_keys: &[VirtualKeyCode]) {
+        for bound in self.keys.iter_mut() {
+            bound.retain(|host_key| !host_keys.contains(host_key));
+        }
+        self.keys[key] = host_keys.to_vec();
+    }
+
+    // key returns the chip8 key pressed by host_key
+    pub fn key(&self, host_key: VirtualKeyCode) -> Option<usize> {
+        self.keys.iter().position(|bound| bound.contains(&host_key))
+    }
+
+    // host_keys returns the host keys that press key
+    pub fn host_keys(&self, key: usize) -> &[VirtualKeyCode] {
+        &self.keys[key]
+    }
+}
+
+// host_key finds a key by its VirtualKeyCode name, ignoring case
+pub fn host_key(name: &str) -> Option<VirtualKeyCode> {
+    HOST_KEYS.iter().copied().find(|key| format!("{:?}", key).eq_ignore_ascii_case(name))
+}
+
+// every VirtualKeyCode, for looking keys up by name
+const HOST_KEYS: &[VirtualKeyCode] = {
+    use VirtualKeyCode::*;
+    &[
+        Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, Key0,
+        A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+        Escape,
+        F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+        F13, F14, F15, F16, F17, F18, F19, F20, F21, F22, F23, F24,
+        PrintScreen, ScrollLock, PauseBreak,
+        Insert, Home, Delete, End, PageDown, PageUp,
+        Left, Up, Right, Down,
+        Backspace, Return, Space, Compose, Caret,
+        Numlock, Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7, Numpad8, Numpad9,
+        NumpadAdd, NumpadDivide, NumpadDecimal, NumpadComma, NumpadEnter, NumpadEquals, NumpadMultiply,
+        NumpadSubtract,
+        AbntC1, AbntC2, Apostrophe, Apps, Asterisk, At, Ax, Backslash, Calculator, Capital, Colon, Comma,
+        Convert, Equals, Grave, Kana, Kanji, LAlt, LBracket, LControl, LShift, LWin, Mail, MediaSelect,
+        MediaStop, Minus, Mute, MyComputer, NavigateForward, NavigateBackward, NextTrack, NoConvert, OEM102,
+        Period, PlayPause, Plus, Power, PrevTrack, RAlt, RBracket, RControl, RShift, RWin, Semicolon, Slash,
+        Sleep, Stop, Sysrq, Tab, Underline, Unlabeled, VolumeDown, VolumeUp, Wake, WebBack, WebFavorites,
+        WebForward, WebHome, WebRefresh, WebSearch, WebStop, Yen, Copy, Paste, Cut,
+    ]
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rom_sections_override_keys() {
+        let text = "\
+            # arrows for the one ROM, WASD for all
+            [rom 0x00000001]
+            4 = Left ; only this ROM
+            [keys]
+            5 = w, Up
+            8 = S, Down
+            [rom DEADBEEF]
+            6 = Right
+            [rom 1]
+            7 = Key1
+        ";
+        let keymap = Keymap::load(text, 1).unwrap();
+        assert_eq!(keymap.host_keys(0x5), [VirtualKeyCode::W, VirtualKeyCode::Up]);
+        assert_eq!(keymap.key(VirtualKeyCode::Left), Some(0x4));
+        assert_eq!(keymap.key(VirtualKeyCode::Q), None);
+        assert_eq!(keymap.key(VirtualKeyCode::Right), None);
+        // Key1 moved from 1 to 7
+        assert_eq!(keymap.key(VirtualKeyCode::Key1), Some(0x7));
+        assert!(keymap.host_keys(0x1).is_empty());
+
+        let keymap = Keymap::load(text, 0xDEADBEEF).unwrap();
+        assert_eq!(keymap.key(VirtualKeyCode::Right), Some(0x6));
+        assert_eq!(keymap.key(VirtualKeyCode::Q), Some(0x4));
+
+        assert_eq!(Keymap::load("[keys]\n5 = Up\nG = W", 0),
+                   Err(KeymapError { line: 3, message: "'G' is not a chip8 key, use 0 to F".to_string() }));
+        assert!(Keymap::load("[keys]\n5 = Upp", 0).is_err());
+        assert!(Keymap::load("5 = Up", 0).is_err());
+    }
+}
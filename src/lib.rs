@@ -1,30 +1,49 @@
 use crate::chip8::Chip8;
 use speedy2d::Window;
 use speedy2d::window::UserEventSender;
+use std::sync::mpsc::Receiver;
 
+pub mod asm;
+pub mod audio;
+pub mod checksum;
 pub mod chip8;
+pub mod cli;
+pub mod debugger;
+pub mod disasm;
+pub mod image;
+pub mod keymap;
+pub mod machine;
+pub mod movie;
+pub mod palette;
 pub mod renderer;
+pub mod rewind;
+pub mod scheduler;
+pub mod trace;
+pub mod video;
 
 
 pub fn run(chip8: Chip8,
-           operations_per_second: u32,
+           instructions_per_frame: u32,
+           settings: renderer::Settings,
+           debug_commands: Option<Receiver<String>>,
+           outputs: renderer::Outputs,
            window: Window,
            user_event_sender: UserEventSender<()>) {
-    let renderer = renderer::Renderer {
+    let emulator = renderer::Emulator {
         chip8,
-        operations_per_second,
-        last_frame_time: std::time::Instant::now(),
-        last_time_tick_time: std::time::Instant::now(),
-        last_instruction_time: std::time::Instant::now(),
-        user_event_sender,
+        clock: machine::FrameClock::new(instructions_per_frame),
+        crash: None,
+        state_slot: 0,
+        state_slots: vec![None; renderer::STATE_SLOTS],
+        rewind: rewind::RewindBuffer::new(settings.rewind),
+        rewinding: false,
+        debugger: debugger::Debugger::new(),
+        debug_commands,
+        audio: outputs.audio,
+        video: outputs.video,
+        held_keys: [false; 16],
+        settings,
     };
+    let renderer = renderer::Renderer::new(emulator, user_event_sender);
     window.run_loop(renderer);
 }
-
-#[cfg(test)]
-mod tests {
-    #[test]
-    fn it_works() {
-        assert!(true);
-    }
-}
\ No newline at end of file
@@ -0,0 +1,298 @@
+use crate::audio::Beeper;
+use crate::chip8::{Chip8, Chip8Error, Screen, StepOutcome, VIP_INTERPRETER_CYCLES};
+use crate::video::Recorder;
+
+pub const FRAMES_PER_SECOND: u32 = 60; // timers and the screen refresh at 60Hz
+
+// FrameClock tells when a 60Hz frame is over. Normally every instruction counts as one and a
+// frame is instructions_per_frame of them. With the vip_timing quirk it counts the machine
+// cycles of the COSMAC VIP interpreter instead: a frame ends once the interpreter used its
+// share of the frame, cycles past the end count towards the next one. Either way a frame
+// also ends when Dxyn waits for the next one (Quirks::waits_for_vblank)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameClock {
+    instructions_per_frame: u32,
+    used: u32, // instructions or VIP machine cycles of the current frame
+    steps: u32, // instructions executed in the current frame
+}
+
+impl FrameClock {
+    // new makes a clock of instructions_per_frame instructions, at least 1
+    pub fn new(instructions_per_frame: u32) -> FrameClock {
+        FrameClock { instructions_per_frame: instructions_per_frame.max(1), used: 0, steps: 0 }
+    }
+
+    pub fn instructions_per_frame(&self) -> u32 {
+        self.instructions_per_frame
+    }
+
+    pub fn set_instructions_per_frame(&mut self, instructions_per_frame: u32) {
+        self.instructions_per_frame = instructions_per_frame.max(1);
+    }
+
+    // is_frame_start returns true while no instruction of the current frame was executed
+    pub fn is_frame_start(&self) -> bool {
+        self.steps == 0
+    }
+
+    // cost returns how much of the frame the next instruction of chip8 will use
+    pub fn cost(&self, chip8: &Chip8) -> u32 {
+        if chip8.quirks().vip_timing { chip8.vip_cycles() } else { 1 }
+    }
+
+    // spend counts an executed instruction of cost, chip8 is the emulator after it. It
+    // returns true when the instruction ended the frame, the clock is then at the start of
+    // the next one
+    pub fn spend(&mut self, chip8: &Chip8, cost: u32) -> bool {
+        self.used += cost;
+        self.steps += 1;
+        let budget = if chip8.quirks().vip_timing { VIP_INTERPRETER_CYCLES } else { self.instructions_per_frame };
+        if self.used < budget {
+            return false;
+        }
+        self.used -= budget;
+        self.steps = 0;
+        true
+    }
+
+    // reset forgets the current frame, for when the emulator state was replaced or the
+    // program waits for the next frame
+    pub fn reset(&mut self) {
+        self.used = 0;
+        self.steps = 0;
+    }
+}
+
+// Machine drives a Chip8 without any window: it executes a frame of instructions as told
+// by its FrameClock and ticks the timers once at the end of every frame.
+// with a Beeper every frame adds its sound: a beep set by Fx18 in frame n starts at sample
+// n * sample_rate / 60 and lasts for as many frames as the sound timer was set to.
+// with a Recorder the screen at the end of every frame is added to the video
+pub struct Machine {
+    chip8: Chip8,
+    clock: FrameClock,
+    cycles: u64, // instructions executed since the start
+    frames: u64, // frames completed since the start
+    exited: bool, // the program executed 00FD
+    audio: Option<Beeper>,
+    video: Option<Recorder>,
+}
+
+impl Machine {
+    // new wraps chip8, instructions_per_frame must be at least 1
+    pub fn new(chip8: Chip8, instructions_per_frame: u32) -> Machine {
+        Machine {
+            chip8,
+            clock: FrameClock::new(instructions_per_frame),
+            cycles: 0,
+            frames: 0,
+            exited: false,
+            audio: None,
+            video: None,
+        }
+    }
+
+    // from_operations_per_second converts an instructions per second speed to a per frame one
+    pub fn from_operations_per_second(chip8: Chip8, operations_per_second: u32) -> Machine {
+        Machine::new(chip8, operations_per_second / FRAMES_PER_SECOND)
+    }
+
+    pub fn chip8(&self) -> &Chip8 {
+        &self.chip8
+    }
+
+    pub fn chip8_mut(&mut self) -> &mut Chip8 {
+        &mut self.chip8
+    }
+
+    // into_chip8 gives the emulator back, timing state is lost
+    pub fn into_chip8(self) -> Chip8 {
+        self.chip8
+    }
+
+    pub fn instructions_per_frame(&self) -> u32 {
+        self.clock.instructions_per_frame()
+    }
+
+    pub fn set_instructions_per_frame(&mut self, instructions_per_frame: u32) {
+        self.clock.set_instructions_per_frame(instructions_per_frame);
+    }
+
+    // set_audio makes every following frame write its sound to audio
+    pub fn set_audio(&mut self, audio: Option<Beeper>) {
+        self.audio = audio;
+    }
+
+    // take_audio removes the Beeper, call finish() on it when the run is over
+    pub fn take_audio(&mut self) -> Option<Beeper> {
+        self.audio.take()
+    }
+
+    // set_video makes every following frame add its screen to video
+    pub fn set_video(&mut self, video: Option<Recorder>) {
+        self.video = video;
+    }
+
+    // take_video removes the Recorder, call finish() on it when the run is over
+    pub fn take_video(&mut self) -> Option<Recorder> {
+        self.video.take()
+    }
+
+    // cycles returns how many instructions were executed (or spent waiting for a key)
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    // frames returns how many 60Hz frames were completed
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    // is_exited returns true once the program stopped the interpreter with 00FD
+    pub fn is_exited(&self) -> bool {
+        self.exited
+    }
+
+    // framebuffer returns the screen as it is right now
+    pub fn framebuffer(&self) -> Screen<'_> {
+        self.chip8.get_screen()
+    }
+
+    // step executes one instruction and ends the frame if it was the last one of it.
+    // waiting for a key takes a cycle too, so timers keep running while the program waits
+    pub fn step(&mut self) -> Result<StepOutcome, Chip8Error> {
+        if self.exited {
+            return Ok(StepOutcome::Exited);
+        }
+        let cost = self.clock.cost(&self.chip8);
+        let outcome = self.chip8.next_instruction()?;
+        if outcome == StepOutcome::Exited {
+            self.exited = true;
+            return Ok(outcome);
+        }
+        // the rest of the frame is spent waiting, Dxyn runs first in the next one
+        if outcome == StepOutcome::WaitingForVblank {
+            self.clock.reset();
+            self.end_frame();
+            return Ok(outcome);
+        }
+        self.cycles += 1;
+        if self.clock.spend(&self.chip8, cost) {
+            self.end_frame();
+        }
+        Ok(outcome)
+    }
+
+    // run_cycles executes up to n instructions, stopping early if the program exits
+    pub fn run_cycles(&mut self, n: u64) -> Result<(), Chip8Error> {
+        for _ in 0..n {
+            if self.step()? == StepOutcome::Exited {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    // run_frame executes the rest of the current frame and ticks the timers
+    pub fn run_frame(&mut self) -> Result<(), Chip8Error> {
+        let frame = self.frames;
+        while self.frames == frame && !self.exited {
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    // run_frames runs n whole frames, stopping early if the program exits
+    pub fn run_frames(&mut self, n: u64) -> Result<(), Chip8Error> {
+        for _ in 0..n {
+            if self.exited {
+                break;
+            }
+            self.run_frame()?;
+        }
+        Ok(())
+    }
+
+    // end_frame writes the sound and the screen of the frame, ticks the 60Hz timers and starts a new frame
+    fn end_frame(&mut self) {
+        if let Some(audio) = &mut self.audio {
+            audio.frame(&self.chip8, true);
+        }
+        if let Some(video) = &mut self.video {
+            video.frame(&self.chip8.get_screen());
+        }
+        self.chip8.timer_tick();
+        self.frames += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::Quirks;
+    use crate::audio::{read_wav, AudioConfig, WavSink};
+
+    fn machine(rom: &[u8], instructions_per_frame: u32) -> Machine {
+        let mut chip8 = Chip8::default();
+        chip8.load_rom(rom.to_vec()).unwrap();
+        Machine::new(chip8, instructions_per_frame)
+    }
+
+    #[test]
+    fn timers_tick_once_per_frame() {
+        // ld v0, 10; ld dt, v0; jp 0x204
+        let mut machine = machine(&[0x60, 0x0A, 0xF0, 0x15, 0x12, 0x04], 10);
+        machine.run_frames(4).unwrap();
+        assert_eq!(machine.frames(), 4);
+        assert_eq!(machine.cycles(), 40);
+        // the first frame set the timer before its own tick
+        assert_eq!(machine.chip8().delay_timer(), 6);
+    }
+
+    #[test]
+    fn sound_is_aligned_to_frames() {
+        // 200: ld v0, 3; 202: ld v1, 0; 204: add v1, 1; 206: se v1, 30; 208: jp 204; 20a: ld st, v0; 20c: jp 20c
+        let rom = [0x60, 0x03, 0x61, 0x00, 0x71, 0x01, 0x31, 0x1E, 0x12, 0x04, 0xF0, 0x18, 0x12, 0x0C];
+        let path = std::env::temp_dir().join(format!("miko_chip8_{}_machine.wav", std::process::id()));
+        let config = AudioConfig { sample_rate: 6000, ..AudioConfig::default() };
+        let mut machine = machine(&rom, 10);
+        machine.set_audio(Some(Beeper::new(config, Box::new(WavSink::create(&path, 6000).unwrap()))));
+        machine.run_frames(12).unwrap();
+        machine.take_audio().unwrap().finish().unwrap();
+
+        let (sample_rate, samples) = read_wav(&std::fs::read(&path).unwrap()).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(sample_rate, 6000);
+        assert_eq!(samples.len(), 12 * 100);
+        // Fx18 is instruction 2 + 29 * 3 + 2 of the ROM, in frame 9, and beeps for 3 frames
+        let sounding: Vec<usize> = (0..samples.len()).filter(|&n| samples[n] != 0).collect();
+        assert_eq!(sounding.first(), Some(&900));
+        assert_eq!(sounding.last(), Some(&1199));
+    }
+
+    #[test]
+    fn vip_timing_counts_machine_cycles() {
+        let vip = |rom: &[u8]| {
+            let mut chip8 = Chip8::new(Quirks { vip_timing: true, ..Quirks::cosmac_vip() });
+            chip8.load_rom(rom.to_vec()).unwrap();
+            Machine::new(chip8, 1000)
+        };
+        // add v0, 1; jp 200: 50 and 52 cycles, --ipf doesn't matter
+        let mut machine = vip(&[0x70, 0x01, 0x12, 0x00]);
+        machine.run_frames(60).unwrap();
+        assert_eq!(VIP_INTERPRETER_CYCLES, 2598);
+        assert_eq!(machine.cycles(), 3057);
+        // drw v0, v0, 5; jp 200: every sprite waits for the next frame
+        let mut machine = vip(&[0xD0, 0x05, 0x12, 0x00]);
+        machine.run_frames(5).unwrap();
+        assert_eq!(machine.cycles(), 10);
+    }
+
+    #[test]
+    fn exit_stops_the_machine() {
+        let mut machine = machine(&[0x00, 0xFD], 10);
+        machine.run_frames(3).unwrap();
+        assert!(machine.is_exited());
+        assert_eq!(machine.frames(), 0);
+    }
+}
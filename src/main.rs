@@ -1,18 +1,241 @@
+use std::env;
 use std::fs;
+use std::io::{self, BufRead};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::mpsc::{self, Receiver};
 use speedy2d::Window;
-use miko_chip8emulator::{chip8, run};
+use speedy2d::window::{WindowCreationOptions, WindowPosition, WindowSize};
+use miko_chip8emulator::{audio, checksum, chip8, debugger, image, keymap, machine, movie, renderer, run, trace, video};
+use miko_chip8emulator::cli::{CliError, Options, USAGE};
 
-fn main() -> Result<(), Box<dyn std::error::Error + 'static>> {
-    let file_path_rom = "example_roms/IBM Logo.ch8";
+fn main() -> ExitCode {
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(CliError::Help) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        },
+        Err(err) => {
+            eprintln!("error: {}\n\n{}", err, USAGE);
+            return ExitCode::from(2);
+        },
+    };
+    match start(options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::FAILURE
+        },
+    }
+}
+
+fn start(mut options: Options) -> Result<(), Box<dyn std::error::Error + 'static>> {
+    let bytes = fs::read(&options.rom)
+        .map_err(|err| format!("cannot read ROM {}: {}", options.rom.display(), err))?;
+
+    // a movie is played back with the settings it was recorded with
+    let playing = match &options.play {
+        Some(path) => {
+            let data = fs::read(path)
+                .map_err(|err| format!("cannot read movie {}: {}", path.display(), err))?;
+            let movie = movie::Movie::load(&data)
+                .map_err(|err| format!("cannot load movie {}: {}", path.display(), err))?;
+            if !movie.matches_rom(&bytes) {
+                return Err(format!("movie {} was recorded with a different ROM", path.display()).into());
+            }
+            options.seed = Some(movie.seed);
+            options.vip_random = movie.vip_random;
+            options.quirks = movie.quirks;
+            options.instructions_per_frame = movie.instructions_per_frame;
+            Some(movie)
+        },
+        None => None,
+    };
+
+    // the VIP generator is seeded with a byte, so is a random seed for it
+    let seed = options.seed.unwrap_or_else(|| if options.vip_random { rand::random::<u8>() as u64 } else { rand::random() });
+    let session = match (&options.record, playing) {
+        (Some(path), _) => Some(movie::Session::Recording {
+            movie: movie::Movie::new(&bytes, seed, options.vip_random, options.quirks, options.instructions_per_frame),
+            path: path.clone(),
+        }),
+        (None, Some(movie)) => Some(movie::Session::Playing { movie, frame: 0 }),
+        (None, None) => None,
+    };
+    let random: Box<dyn chip8::RandomSource> = if options.vip_random {
+        Box::new(chip8::VipRandom::new(seed as u8))
+    } else {
+        Box::new(chip8::SeededRandom::new(seed))
+    };
+    let rom_checksum = checksum::crc32(&bytes); // picks the [rom] sections of the keymap
+    let mut chip8 = chip8::Chip8::with_random(options.quirks, random);
+    chip8.load_rom(bytes)
+        .map_err(|err| format!("cannot load ROM {}: {}", options.rom.display(), err))?;
+
+    if let Some(path) = &options.load_state {
+        let state = fs::read(path)
+            .map_err(|err| format!("cannot read save state {}: {}", path.display(), err))?;
+        chip8.load_state(&state)
+            .map_err(|err| format!("cannot load save state {}: {}", path.display(), err))?;
+    }
 
-    let bytes = fs::read(file_path_rom)?;
+    if let Some(config) = &options.trace {
+        let tracer = trace::FileTracer::create(config.clone())
+            .map_err(|err| format!("cannot create trace file {}: {}", config.path.display(), err))?;
+        chip8.set_trace_sink(Some(Box::new(tracer)));
+    }
 
-    let window =
-        Window::new_centered("Meow", (640, 480)).unwrap();
+    if options.headless {
+        return run_headless(chip8, &options, session);
+    }
+
+    let title = format!("miko_chip8emulator - {}",
+                        options.rom.file_name().unwrap_or_default().to_string_lossy());
+    let window_options = if options.fullscreen {
+        WindowCreationOptions::new_fullscreen_borderless()
+    } else {
+        let size = (chip8::LORES_WIDTH as u32 * options.scale, chip8::LORES_HEIGHT as u32 * options.scale);
+        WindowCreationOptions::new_windowed(WindowSize::PhysicalPixels(size.into()), Some(WindowPosition::Center))
+    };
+    let window = Window::new_with_options(&title, window_options)
+        .map_err(|err| format!("cannot open window: {:?}", err))?;
 
     let user_event_sender = window.create_user_event_sender();
-    let mut chip8 = chip8::Chip8::new();
-    chip8.load_rom(bytes);
-    run(chip8, 500, window, user_event_sender);
+    let settings = renderer::Settings {
+        palette: options.palette,
+        paused: options.paused,
+        muted: options.mute,
+        state_path: Some(options.rom.clone()),
+        rewind: options.rewind,
+        max_catch_up: options.max_catch_up,
+        screenshot_scale: options.scale as usize,
+        video: video_config(&options),
+        keymap: load_keymap(&options, rom_checksum)?,
+        movie: session,
+    };
+    let debug_commands = options.debug.then(read_debug_commands);
+    let outputs = renderer::Outputs { audio: open_audio(&options)?, video: open_video(&options)? };
+    run(chip8,
+        options.instructions_per_frame,
+        settings,
+        debug_commands,
+        outputs,
+        window,
+        user_event_sender);
+    Ok(())
+}
+
+// open_audio opens the sound output: the WAV file of --audio-out, else the sound device.
+// without a device the emulator stays silent
+fn open_audio(options: &Options) -> Result<Option<audio::Beeper>, Box<dyn std::error::Error + 'static>> {
+    let sink: Box<dyn audio::AudioSink> = match &options.audio_out {
+        Some(path) => Box::new(audio::WavSink::create(path, options.audio.sample_rate)
+            .map_err(|err| format!("cannot create {}: {}", path.display(), err))?),
+        None => match audio::DeviceSink::open(options.audio.sample_rate) {
+            Ok(device) => Box::new(device),
+            Err(err) => {
+                eprintln!("no sound: {}", err);
+                return Ok(None);
+            },
+        },
+    };
+    Ok(Some(audio::Beeper::new(options.audio, sink)))
+}
+
+// load_keymap reads the keymap of --keymap, or the one in the config directory if there is one,
+// with the overrides of the ROM whose CRC-32 is rom_checksum
+fn load_keymap(options: &Options, rom_checksum: u32) -> Result<keymap::Keymap, Box<dyn std::error::Error + 'static>> {
+    let path = match &options.keymap {
+        Some(path) => path.clone(),
+        None => {
+            let config = env::var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+                .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+            match config.map(|config| config.join("miko_chip8emulator").join("keymap.ini")) {
+                Some(path) if path.exists() => path,
+                _ => return Ok(keymap::Keymap::default()),
+            }
+        },
+    };
+    let text = fs::read_to_string(&path)
+        .map_err(|err| format!("cannot read keymap {}: {}", path.display(), err))?;
+    let keymap = keymap::Keymap::load(&text, rom_checksum)
+        .map_err(|err| format!("{}:{}", path.display(), err))?;
+    Ok(keymap)
+}
+
+fn video_config(options: &Options) -> video::VideoConfig {
+    video::VideoConfig { scale: options.scale as usize, frame_skip: options.frame_skip, palette: options.palette }
+}
+
+// open_video starts the recording of --video
+fn open_video(options: &Options) -> Result<Option<video::Recorder>, Box<dyn std::error::Error + 'static>> {
+    let Some(path) = &options.video else {
+        return Ok(None);
+    };
+    let recorder = video::Recorder::create(path, video_config(options))
+        .map_err(|err| format!("cannot create {}: {}", path.display(), err))?;
+    Ok(Some(recorder))
+}
+
+// read_debug_commands forwards the lines typed on stdin to the window
+fn read_debug_commands() -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        println!("debugger ready, type help for a list of commands");
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if !line.trim().is_empty() && sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+// run_headless runs the requested number of frames without a window and dumps the screen,
+// with --debug the frames are run first and then the debugger prompt takes over.
+// a played movie runs for as many frames as it has unless --frames says otherwise,
+// --audio-out writes the sound and --video the screens of all the frames that ran
+fn run_headless(chip8: chip8::Chip8, options: &Options, session: Option<movie::Session>)
+                -> Result<(), Box<dyn std::error::Error + 'static>> {
+    let mut machine = machine::Machine::new(chip8, options.instructions_per_frame);
+    if let Some(path) = &options.audio_out {
+        let wav = audio::WavSink::create(path, options.audio.sample_rate)
+            .map_err(|err| format!("cannot create {}: {}", path.display(), err))?;
+        machine.set_audio(Some(audio::Beeper::new(options.audio, Box::new(wav))));
+    }
+    machine.set_video(open_video(options)?);
+    let result = match session {
+        Some(movie::Session::Playing { movie, .. }) =>
+            movie::play(&mut machine, &movie, options.frames.unwrap_or(movie.len() as u64)),
+        _ => machine.run_frames(options.frames.unwrap_or_default()),
+    };
+    if result.is_ok() && options.debug {
+        let mut debugger = debugger::Debugger::new();
+        debugger::repl(&mut machine, &mut debugger, io::stdin().lock(), io::stdout())?;
+    }
+    // the sound so far is kept even if the emulator crashed
+    if let (Some(mut audio), Some(path)) = (machine.take_audio(), &options.audio_out) {
+        audio.finish().map_err(|err| format!("cannot write {}: {}", path.display(), err))?;
+    }
+    if let (Some(video), Some(path)) = (machine.take_video(), &options.video) {
+        video.finish().map_err(|err| format!("cannot write {}: {}", path.display(), err))?;
+    }
+    result.map_err(|err| format!("emulator crashed after {} frames: {}", machine.frames(), err))?;
+
+    if let Some(path) = &options.screenshot {
+        image::Image::from_screen(&machine.framebuffer(), &options.palette, options.scale as usize).save(path)
+            .map_err(|err| format!("cannot write {}: {}", path.display(), err))?;
+    }
+    let screen = machine.framebuffer().to_string();
+    match &options.dump_screen {
+        Some(path) => fs::write(path, screen)
+            .map_err(|err| format!("cannot write {}: {}", path.display(), err))?,
+        None if !options.debug && options.screenshot.is_none() && options.video.is_none() => print!("{}", screen),
+        None => (),
+    }
     Ok(())
 }
@@ -0,0 +1,240 @@
+use std::fmt;
+use std::path::PathBuf;
+use crate::checksum::crc32;
+use crate::chip8::{Chip8Error, Quirks};
+use crate::machine::Machine;
+
+// a movie file is MAGIC, VERSION, the settings a run depends on (ROM checksum, seed,
+// random source, quirks, instructions per frame), the number of frames and the keys
+// as runs of (keyboard, frames), then the CRC-32 of all the bytes before it.
+// numbers are little endian, a keyboard is a u16 with bit n set while key n is held
+const MAGIC: &[u8; 4] = b"C8MV";
+const VERSION: u8 = 1;
+
+// Movie is the keyboard of every 60Hz frame of a run that started at power on. Played back
+// with the same ROM and settings it does exactly what the recorded run did
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_checksum: u32, // CRC-32 of the ROM file
+    pub seed: u64,
+    pub vip_random: bool, // CXNN used VipRandom instead of SeededRandom
+    pub quirks: Quirks,
+    pub instructions_per_frame: u32,
+    frames: Vec<u16>,
+}
+
+// MovieError is returned by Movie::load when the bytes are not a usable movie
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MovieError {
+    BadMagic, // not a movie at all
+    UnsupportedVersion(u8), // written by a newer emulator
+    ChecksumMismatch, // the file is damaged
+    Truncated, // ends before all of the movie was read
+    Invalid(&'static str), // a value that can't be played back
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MovieError::BadMagic => write!(f, "not a chip8 movie"),
+            MovieError::UnsupportedVersion(version) =>
+                write!(f, "unsupported movie version {} (expected {})", version, VERSION),
+            MovieError::ChecksumMismatch => write!(f, "movie checksum mismatch"),
+            MovieError::Truncated => write!(f, "movie is truncated"),
+            MovieError::Invalid(what) => write!(f, "movie has invalid {}", what),
+        }
+    }
+}
+
+impl std::error::Error for MovieError {}
+
+impl Movie {
+    // new starts an empty movie of rom run with the given settings
+    pub fn new(rom: &[u8], seed: u64, vip_random: bool, quirks: Quirks, instructions_per_frame: u32) -> Movie {
+        Movie {
+            rom_checksum: crc32(rom),
+            seed,
+            vip_random,
+            quirks,
+            instructions_per_frame,
+            frames: Vec::new(),
+        }
+    }
+
+    // len returns the number of recorded frames
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    // matches_rom returns true if the movie was recorded with this ROM
+    pub fn matches_rom(&self, rom: &[u8]) -> bool {
+        crc32(rom) == self.rom_checksum
+    }
+
+    // push records the keys held during the next frame
+    pub fn push(&mut self, keyboard: &[bool; 16]) {
+        let keys = keyboard.iter().enumerate()
+            .fold(0u16, |keys, (key, pressed)| keys | (*pressed as u16) << key);
+        self.frames.push(keys);
+    }
+
+    // truncate forgets the frames from len on, used when a recording is rewound
+    pub fn truncate(&mut self, len: usize) {
+        self.frames.truncate(len);
+    }
+
+    // keys returns the keys held during a frame, None after the end of the movie
+    pub fn keys(&self, frame: usize) -> Option<[bool; 16]> {
+        let keys = *self.frames.get(frame)?;
+        Some(std::array::from_fn(|key| keys & (1 << key) != 0))
+    }
+
+    pub fn save(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(64);
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        out.extend_from_slice(&self.rom_checksum.to_le_bytes());
+        out.extend_from_slice(&self.seed.to_le_bytes());
+        out.push(self.vip_random as u8);
+        out.extend_from_slice(&self.quirks.to_bytes());
+        out.extend_from_slice(&self.instructions_per_frame.to_le_bytes());
+        out.extend_from_slice(&(self.frames.len() as u32).to_le_bytes());
+        for run in self.frames.chunk_by(|a, b| a == b) {
+            out.extend_from_slice(&run[0].to_le_bytes());
+            out.extend_from_slice(&(run.len() as u32).to_le_bytes());
+        }
+        let checksum = crc32(&out);
+        out.extend_from_slice(&checksum.to_le_bytes());
+        out
+    }
+
+    pub fn load(data: &[u8]) -> Result<Movie, MovieError> {
+        if data.len() < MAGIC.len() + 1 || &data[..MAGIC.len()] != MAGIC {
+            return Err(MovieError::BadMagic);
+        }
+        let version = data[MAGIC.len()];
+        if version != VERSION {
+            return Err(MovieError::UnsupportedVersion(version));
+        }
+        if data.len() < MAGIC.len() + 1 + 4 {
+            return Err(MovieError::Truncated);
+        }
+        let (body, checksum) = data.split_at(data.len() - 4);
+        if crc32(body) != u32::from_le_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]) {
+            return Err(MovieError::ChecksumMismatch);
+        }
+
+        let mut input = &body[MAGIC.len() + 1..];
+        let rom_checksum = u32::from_le_bytes(take(&mut input)?);
+        let seed = u64::from_le_bytes(take(&mut input)?);
+        let vip_random = match take::<1>(&mut input)? {
+            [0] => false,
+            [1] => true,
+            _ => return Err(MovieError::Invalid("random source")),
+        };
+        let quirks = Quirks::from_bytes(take(&mut input)?).map_err(MovieError::Invalid)?;
+        let instructions_per_frame = u32::from_le_bytes(take(&mut input)?);
+        if instructions_per_frame == 0 {
+            return Err(MovieError::Invalid("instructions per frame"));
+        }
+        let len = u32::from_le_bytes(take(&mut input)?) as usize;
+        let mut frames = Vec::with_capacity(len.min(body.len() * 8));
+        while frames.len() < len {
+            let keys = u16::from_le_bytes(take(&mut input)?);
+            let count = u32::from_le_bytes(take(&mut input)?) as usize;
+            if count == 0 || frames.len() + count > len {
+                return Err(MovieError::Invalid("frame count"));
+            }
+            frames.resize(frames.len() + count, keys);
+        }
+        if !input.is_empty() {
+            return Err(MovieError::Invalid("length"));
+        }
+        Ok(Movie { rom_checksum, seed, vip_random, quirks, instructions_per_frame, frames })
+    }
+}
+
+fn take<const N: usize>(input: &mut &[u8]) -> Result<[u8; N], MovieError> {
+    if input.len() < N {
+        return Err(MovieError::Truncated);
+    }
+    let (bytes, rest) = input.split_at(N);
+    *input = rest;
+    Ok(bytes.try_into().unwrap())
+}
+
+// Session is a movie the window is recording or playing. While there is one, the window
+// runs frame by frame like Machine does, so what is recorded can be played back exactly
+#[derive(Debug, Clone)]
+pub enum Session {
+    Recording { movie: Movie, path: PathBuf }, // saved to path as it grows
+    Playing { movie: Movie, frame: usize }, // frame is the next one to play
+}
+
+// play runs frames frames of movie on machine, which must have been set up with the movie's
+// ROM and settings. No keys are held after the end of the movie, a program that exits stops it
+pub fn play(machine: &mut Machine, movie: &Movie, frames: u64) -> Result<(), Chip8Error> {
+    for frame in 0..frames {
+        if machine.is_exited() {
+            break;
+        }
+        machine.chip8_mut().set_keys(movie.keys(frame as usize).unwrap_or_default());
+        machine.run_frame()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::Chip8;
+
+    // draws a random digit and counts in v2 whenever key 0 is held
+    const ROM: [u8; 20] = [
+        0x60, 0x00, // 200: ld v0, 0
+        0xE0, 0xA1, // 202: sknp v0
+        0x12, 0x0A, // 204: jp 20a
+        0x12, 0x02, // 206: jp 202
+        0x00, 0x00, // 208: (unused)
+        0xC1, 0x0F, // 20a: rnd v1, 0x0f
+        0xF1, 0x29, // 20c: ld f, v1
+        0xD0, 0x05, // 20e: drw v0, v0, 5
+        0x72, 0x01, // 210: add v2, 1
+        0x12, 0x02, // 212: jp 202
+    ];
+
+    fn machine(movie: &Movie) -> Machine {
+        let mut chip8 = Chip8::with_seed(movie.quirks, movie.seed);
+        chip8.load_rom(ROM.to_vec()).unwrap();
+        Machine::new(chip8, movie.instructions_per_frame)
+    }
+
+    #[test]
+    fn replays_a_recording() {
+        let mut movie = Movie::new(&ROM, 42, false, Quirks::cosmac_vip(), 8);
+        let mut recorded = machine(&movie);
+        for frame in 0..100 {
+            let mut keyboard = [false; 16];
+            keyboard[0] = frame % 7 < 3;
+            recorded.chip8_mut().set_keys(keyboard);
+            movie.push(&keyboard);
+            recorded.run_frame().unwrap();
+        }
+
+        let loaded = Movie::load(&movie.save()).unwrap();
+        assert_eq!(loaded, movie);
+        assert!(loaded.matches_rom(&ROM));
+        let mut played = machine(&loaded);
+        play(&mut played, &loaded, loaded.len() as u64).unwrap();
+        assert_eq!(played.chip8().save_state(), recorded.chip8().save_state());
+        assert_ne!(played.chip8().registers()[2], 0);
+
+        let mut damaged = movie.save();
+        damaged[10] ^= 1;
+        assert_eq!(Movie::load(&damaged), Err(MovieError::ChecksumMismatch));
+    }
+}
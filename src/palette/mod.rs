@@ -0,0 +1,66 @@
+// Palette holds the 0xRRGGBB colors of the four possible pixel values:
+// off, first plane, second plane and both planes (the last two are only used by XO-CHIP)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    pub colors: [u32; 4],
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette {
+            colors: [0x000000, 0xFFFFFF, 0xAAAAAA, 0x555555],
+        }
+    }
+}
+
+impl Palette {
+    // color returns the 0xRRGGBB color of a pixel value
+    pub fn color(&self, pixel: u8) -> u32 {
+        self.colors[(pixel & 0x3) as usize]
+    }
+
+    // rgb returns the color of a pixel value split into bytes
+    pub fn rgb(&self, pixel: u8) -> [u8; 3] {
+        let color = self.color(pixel);
+        [(color >> 16) as u8, (color >> 8) as u8, color as u8]
+    }
+
+    // parse reads a comma separated list of up to four colors, missing ones keep their default
+    pub fn parse(list: &str) -> Option<Palette> {
+        let mut palette = Palette::default();
+        let colors: Vec<&str> = list.split(',').collect();
+        if colors.len() > palette.colors.len() {
+            return None;
+        }
+        for (i, color) in colors.iter().enumerate() {
+            palette.colors[i] = parse_color(color)?;
+        }
+        Some(palette)
+    }
+}
+
+// parse_color reads a color written as RRGGBB, #RRGGBB, 0xRRGGBB or a basic color name
+pub fn parse_color(color: &str) -> Option<u32> {
+    let color = color.trim().to_ascii_lowercase();
+    let named = match color.as_str() {
+        "black" => Some(0x000000),
+        "white" => Some(0xFFFFFF),
+        "gray" | "grey" => Some(0x808080),
+        "red" => Some(0xFF0000),
+        "green" => Some(0x00FF00),
+        "blue" => Some(0x0000FF),
+        "yellow" => Some(0xFFFF00),
+        "amber" => Some(0xFFB000),
+        _ => None,
+    };
+    if named.is_some() {
+        return named;
+    }
+    let hex = color.strip_prefix('#')
+        .or_else(|| color.strip_prefix("0x"))
+        .unwrap_or(&color);
+    if hex.len() != 6 {
+        return None;
+    }
+    u32::from_str_radix(hex, 16).ok()
+}
@@ -0,0 +1,515 @@
+use std::path::PathBuf;
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::time::Instant;
+use speedy2d::window::UserEventSender;
+use crate::audio::Beeper;
+use crate::chip8::{Chip8, Chip8Error, StepOutcome};
+use crate::debugger::{format_registers, Command, Debugger, Stop, Target};
+use crate::image::Image;
+use crate::machine::FrameClock;
+use crate::movie::Session;
+use crate::rewind::RewindBuffer;
+use crate::scheduler::Scheduler;
+use crate::video::Recorder;
+use super::frames::{Frame, FrameSender};
+use super::{Settings, STATE_SLOTS};
+
+// Control is something the window asks the emulator thread to do
+pub enum Control {
+    Key(usize, bool), // a chip8 key was pressed or released
+    TogglePause,
+    ToggleMute,
+    DebugStep(fn(&mut Debugger, &Chip8)), // starts a debugger goal like Debugger::step
+    SaveSlot,
+    LoadSlot,
+    SelectSlot(isize), // moves the current slot by this much
+    Rewind(bool), // Backspace pressed or released
+    Screenshot,
+    ToggleVideo,
+    Quit,
+}
+
+// Emulator is everything that runs on the emulator thread: every 60Hz frame it executes
+// a frame of instructions through the debugger, ticks the timers, writes sound
+// and video and publishes the screen to the window
+pub struct Emulator {
+    pub chip8: Chip8,
+    pub clock: FrameClock, // tells when a frame is over, like in Machine
+    pub crash: Option<Chip8Error>, // set when the program faulted, emulation is stopped
+    pub settings: Settings,
+    pub state_slot: usize, // slot used by F5 and F9
+    pub state_slots: Vec<Option<Vec<u8>>>, // states saved during this session, one per slot
+    pub rewind: RewindBuffer, // one state per frame
+    pub rewinding: bool, // Backspace is held, frames go backwards instead of forwards
+    pub debugger: Debugger, // every instruction runs through it, so breakpoints always work
+    pub debug_commands: Option<Receiver<String>>, // lines typed at the stdin debugger prompt
+    pub audio: Option<Beeper>, // plays the sound timer, None if there is no sound at all
+    pub video: Option<Recorder>, // video being recorded, F2 starts and stops it
+    pub held_keys: [bool; 16], // keys held on the host, given to the emulator at the next frame during a movie
+}
+
+// FrameCounter counts the instructions the debugger executes on the frame clock, so a
+// frame that stopped at a breakpoint continues where it was
+struct FrameCounter<'a> {
+    chip8: &'a mut Chip8,
+    clock: &'a mut FrameClock,
+    frame_over: bool,
+}
+
+impl Target for FrameCounter<'_> {
+    fn chip8(&self) -> &Chip8 {
+        self.chip8
+    }
+
+    fn step(&mut self) -> Result<StepOutcome, Chip8Error> {
+        let cost = self.clock.cost(self.chip8);
+        let outcome = self.chip8.next_instruction()?;
+        match outcome {
+            StepOutcome::Exited => (),
+            StepOutcome::WaitingForVblank => {
+                self.clock.reset();
+                self.frame_over = true;
+            },
+            _ => self.frame_over = self.clock.spend(self.chip8, cost),
+        }
+        Ok(outcome)
+    }
+}
+
+impl Emulator {
+    // run is the emulator thread: the scheduler runs a frame every 60th of a second, catching
+    // up after stalls, and the window is woken up when the newest screen is published.
+    // it ends when the program exits or the window asks to quit
+    pub fn run(mut self, controls: Receiver<Control>, mut frames: FrameSender, user_event_sender: UserEventSender<()>) {
+        let mut scheduler = Scheduler::new(Instant::now(), self.settings.max_catch_up);
+        'frames: loop {
+            for _ in 0..scheduler.due(Instant::now()) {
+                if !self.handle_controls(&controls) || !self.frame() {
+                    break 'frames;
+                }
+            }
+            if !frames.publish(|frame| self.draw(frame)) || user_event_sender.send_event(()).is_err() {
+                break;
+            }
+            if let Some(wait) = scheduler.next_frame().checked_duration_since(Instant::now()) {
+                std::thread::sleep(wait);
+            }
+        }
+        if scheduler.dropped() > 0 {
+            println!("{} frames were skipped because the emulator fell behind", scheduler.dropped());
+        }
+        // the window closes once it is woken up and finds no more frames coming
+        drop(frames);
+        let _ = user_event_sender.send_event(());
+    }
+
+    // handle_controls does what the window asked for since the last frame, returns false to quit
+    fn handle_controls(&mut self, controls: &Receiver<Control>) -> bool {
+        loop {
+            let control = match controls.try_recv() {
+                Ok(control) => control,
+                Err(TryRecvError::Empty) => return true,
+                Err(TryRecvError::Disconnected) => return false,
+            };
+            match control {
+                Control::Key(key, pressed) => self.set_key(key, pressed),
+                Control::TogglePause => self.toggle_pause(),
+                Control::ToggleMute => self.settings.muted = !self.settings.muted,
+                Control::DebugStep(start) => self.debug_step(start),
+                Control::SaveSlot => self.save_slot(),
+                Control::LoadSlot => self.load_slot(),
+                Control::SelectSlot(delta) => self.select_slot(delta),
+                // while paused it steps back one frame
+                Control::Rewind(true) if self.settings.paused => self.rewind_frame(),
+                Control::Rewind(rewinding) => self.rewinding = rewinding,
+                Control::Screenshot => self.screenshot(),
+                Control::ToggleVideo => self.toggle_video(),
+                Control::Quit => return false,
+            }
+        }
+    }
+
+    // frame runs one 60Hz frame, returns false when the program exited
+    fn frame(&mut self) -> bool {
+        if self.rewinding {
+            self.rewind_frame();
+        } else if self.crash.is_none() && !self.settings.paused {
+            self.rewind.push(self.chip8.save_state());
+        }
+        self.chip8.flush_trace();
+
+        if !self.poll_debug_commands() {
+            return false;
+        }
+        if self.crash.is_some() || self.settings.paused || self.rewinding {
+            // silence is written while nothing runs, so the sound device doesn't run dry
+            if let Some(audio) = &mut self.audio {
+                audio.frame(&self.chip8, false);
+            }
+            return true;
+        }
+        self.run_frame()
+    }
+
+    // draw copies the screen into a frame for the window, with the registers while paused
+    fn draw(&self, frame: &mut Frame) {
+        let screen = self.chip8.get_screen();
+        frame.width = screen.width;
+        frame.height = screen.height;
+        frame.pixels.clear();
+        frame.pixels.extend_from_slice(screen.pixels);
+        frame.crashed = self.crash.is_some();
+        frame.overlay = (self.settings.paused || self.crash.is_some()).then(|| {
+            let status = match &self.crash {
+                Some(_) => "CRASHED, F9 LOADS A STATE",
+                None => "P RUN  F11 STEP  F10 NEXT  F8 OUT",
+            };
+            format!("{}\n{}", format_registers(&self.chip8), status)
+        });
+    }
+
+    // state_file returns the file of a save state slot, if states are written to disk
+    fn state_file(&self, slot: usize) -> Option<PathBuf> {
+        self.settings.state_path.as_ref().map(|path| {
+            let mut file = path.clone().into_os_string();
+            file.push(format!(".state{}", slot));
+            PathBuf::from(file)
+        })
+    }
+
+    // save_slot snapshots the emulator into the current slot
+    fn save_slot(&mut self) {
+        let state = self.chip8.save_state();
+        if let Some(file) = self.state_file(self.state_slot) {
+            match std::fs::write(&file, &state) {
+                Ok(()) => println!("saved state to {}", file.display()),
+                Err(err) => eprintln!("cannot save state to {}: {}", file.display(), err),
+            }
+        } else {
+            println!("saved state to slot {}", self.state_slot);
+        }
+        self.state_slots[self.state_slot] = Some(state);
+    }
+
+    // load_slot restores the emulator from the current slot. The file wins over memory, the
+    // state saved in memory is used when there is no file, like when it couldn't be written
+    fn load_slot(&mut self) {
+        let memory = self.state_slots[self.state_slot].clone();
+        let state = match self.state_file(self.state_slot) {
+            Some(file) => match std::fs::read(&file) {
+                Ok(state) => Some(state),
+                Err(err) if memory.is_none() => {
+                    eprintln!("cannot read state from {}: {}", file.display(), err);
+                    None
+                },
+                Err(_) => memory,
+            },
+            None => memory,
+        };
+        let Some(state) = state else {
+            eprintln!("slot {} is empty", self.state_slot);
+            return;
+        };
+        match self.chip8.load_state(&state) {
+            Ok(()) => {
+                println!("loaded state from slot {}", self.state_slot);
+                self.crash = None;
+            },
+            Err(err) => eprintln!("cannot load state from slot {}: {}", self.state_slot, err),
+        }
+    }
+
+    // select_slot moves the current slot by delta, wrapping around
+    fn select_slot(&mut self, delta: isize) {
+        self.state_slot = (self.state_slot as isize + delta).rem_euclid(STATE_SLOTS as isize) as usize;
+        println!("state slot {}", self.state_slot);
+    }
+
+    // rewind_frame goes one frame back, keys that are held right now stay held
+    fn rewind_frame(&mut self) {
+        let Some(state) = self.rewind.pop() else {
+            return;
+        };
+        let keys = self.chip8.keys();
+        if let Err(err) = self.chip8.load_state(&state) {
+            eprintln!("cannot rewind: {}", err);
+            self.rewind.clear();
+            return;
+        }
+        self.chip8.set_keys(keys);
+        self.crash = None;
+        // the movie goes back with the emulator, a recording continues from here
+        self.clock.reset();
+        match &mut self.settings.movie {
+            Some(Session::Recording { movie, .. }) => movie.truncate(movie.len().saturating_sub(1)),
+            Some(Session::Playing { frame, .. }) => *frame = frame.saturating_sub(1),
+            None => (),
+        }
+    }
+
+    // set_key changes a chip8 key from the host keyboard. During a movie keys only change
+    // at frame boundaries, and not at all while one is played back
+    fn set_key(&mut self, key: usize, pressed: bool) {
+        match self.settings.movie {
+            Some(Session::Playing { .. }) => (),
+            Some(Session::Recording { .. }) => self.held_keys[key] = pressed,
+            None if pressed => self.chip8.key_down(key),
+            None => self.chip8.key_up(key),
+        }
+    }
+
+    // save_movie writes the recording to its file
+    fn save_movie(&self) {
+        if let Some(Session::Recording { movie, path }) = &self.settings.movie {
+            if let Err(err) = std::fs::write(path, movie.save()) {
+                eprintln!("cannot save movie to {}: {}", path.display(), err);
+            }
+        }
+    }
+
+    // run_frame runs one 60Hz frame like Machine::run_frame: the keys of a movie frame are
+    // recorded or played back first, then instructions run until the clock ends the frame
+    // and the timers tick. A breakpoint can stop it in the middle, the next call finishes the frame.
+    // returns false when the program exited
+    fn run_frame(&mut self) -> bool {
+        if self.clock.is_frame_start() {
+            match &mut self.settings.movie {
+                Some(Session::Recording { movie, .. }) => {
+                    self.chip8.set_keys(self.held_keys);
+                    movie.push(&self.held_keys);
+                    // saved once a second, little is lost if the process is killed
+                    if movie.len() % 60 == 0 {
+                        self.save_movie();
+                    }
+                },
+                Some(Session::Playing { movie, frame }) => match movie.keys(*frame) {
+                    Some(keys) => {
+                        self.chip8.set_keys(keys);
+                        *frame += 1;
+                    },
+                    None => {
+                        println!("movie ended after {} frames", frame);
+                        self.settings.movie = None;
+                        self.settings.paused = true;
+                        return true;
+                    },
+                },
+                None => (),
+            }
+        }
+
+        if !self.debugger.is_running() {
+            self.debugger.resume(&self.chip8);
+        }
+        let mut counter = FrameCounter { chip8: &mut self.chip8, clock: &mut self.clock, frame_over: false };
+        let mut stop = None;
+        while !counter.frame_over && stop.is_none() {
+            stop = self.debugger.run(&mut counter, 1);
+        }
+        // the sound and screen of the frame are written before the tick, like Machine::end_frame
+        // does, so recordings match headless ones. the video only shows gameplay, it stands
+        // still while paused or rewinding
+        if counter.frame_over {
+            if let Some(audio) = &mut self.audio {
+                audio.frame(&self.chip8, !self.settings.muted);
+            }
+            if let Some(video) = &mut self.video {
+                video.frame(&self.chip8.get_screen());
+            }
+            self.chip8.timer_tick();
+        }
+        match stop {
+            Some(stop) => self.on_stop(stop),
+            None => true,
+        }
+    }
+
+    // poll_debug_commands executes the commands typed at the debugger prompt since the last
+    // frame, returns false on quit
+    fn poll_debug_commands(&mut self) -> bool {
+        let Some(commands) = &self.debug_commands else {
+            return true;
+        };
+        let lines: Vec<String> = commands.try_iter().collect();
+        for line in lines {
+            let command = match Command::parse(&line) {
+                Ok(command) => command,
+                Err(message) => {
+                    println!("{}", message);
+                    continue;
+                },
+            };
+            if command == Command::Quit {
+                return false;
+            }
+            let answer = self.debugger.execute(&self.chip8, command);
+            if !answer.is_empty() {
+                println!("{}", answer);
+            }
+            if self.debugger.is_running() {
+                self.settings.paused = false;
+            }
+        }
+        true
+    }
+
+    // debug_step starts a debugger goal such as a single step and lets the emulator run towards it
+    fn debug_step(&mut self, start: fn(&mut Debugger, &Chip8)) {
+        if self.crash.is_some() {
+            return;
+        }
+        start(&mut self.debugger, &self.chip8);
+        self.settings.paused = false;
+    }
+
+    // toggle_pause pauses or resumes, resuming runs until the next breakpoint
+    fn toggle_pause(&mut self) {
+        if self.settings.paused {
+            self.debugger.resume(&self.chip8);
+            self.settings.paused = false;
+        } else {
+            self.debugger.pause();
+            self.settings.paused = true;
+        }
+    }
+
+    // on_stop handles the debugger stopping: the program exited, crashed or reached a breakpoint.
+    // returns false when the program exited
+    fn on_stop(&mut self, stop: Stop) -> bool {
+        self.settings.paused = true;
+        match stop {
+            Stop::Exited => return false,
+            Stop::Crashed(err) => {
+                eprintln!("emulator crashed: {}", err);
+                eprintln!("{}", format_registers(&self.chip8));
+                self.crash = Some(err);
+            },
+            Stop::Breakpoint(index) => {
+                println!("{}: {}", stop, self.debugger.breakpoints()[index]);
+                println!("{}", format_registers(&self.chip8));
+            },
+            Stop::Done => {
+                if self.debug_commands.is_some() {
+                    println!("{}", format_registers(&self.chip8));
+                }
+            },
+        }
+        true
+    }
+
+    // screenshot saves the screen as <state_path>.shotN.png with the first free N,
+    // in the working directory if there is no state path
+    fn screenshot(&self) {
+        let base = self.settings.state_path.clone().unwrap_or_else(|| PathBuf::from("screenshot"));
+        let path = (0..).map(|n| {
+            let mut file = base.clone().into_os_string();
+            file.push(format!(".shot{}.png", n));
+            PathBuf::from(file)
+        }).find(|path| !path.exists()).unwrap();
+        let image = Image::from_screen(&self.chip8.get_screen(), &self.settings.palette, self.settings.screenshot_scale);
+        match image.save(&path) {
+            Ok(()) => println!("saved screenshot to {}", path.display()),
+            Err(err) => eprintln!("cannot save screenshot to {}: {}", path.display(), err),
+        }
+    }
+
+    // toggle_video stops the video being recorded, or starts recording <state_path>.clipN.gif
+    // with the first free N
+    fn toggle_video(&mut self) {
+        if let Some(video) = self.video.take() {
+            let frames = video.frames_written();
+            match video.finish() {
+                Ok(()) => println!("video stopped after {} frames", frames),
+                Err(err) => eprintln!("video failed: {}", err),
+            }
+            return;
+        }
+        let base = self.settings.state_path.clone().unwrap_or_else(|| PathBuf::from("clip"));
+        let path = (0..).map(|n| {
+            let mut file = base.clone().into_os_string();
+            file.push(format!(".clip{}.gif", n));
+            PathBuf::from(file)
+        }).find(|path| !path.exists()).unwrap();
+        match Recorder::create(&path, self.settings.video) {
+            Ok(video) => {
+                println!("recording video to {}", path.display());
+                self.video = Some(video);
+            },
+            Err(err) => eprintln!("cannot record video to {}: {}", path.display(), err),
+        }
+    }
+}
+
+impl Drop for Emulator {
+    // a recording is also saved when the window is closed, and the sound output and video finished
+    fn drop(&mut self) {
+        self.save_movie();
+        if let Some(Err(err)) = self.audio.as_mut().map(Beeper::finish) {
+            eprintln!("sound output failed: {}", err);
+        }
+        if let Some(Err(err)) = self.video.take().map(Recorder::finish) {
+            eprintln!("video failed: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::{read_wav, AudioConfig, WavSink};
+    use crate::rewind::RewindConfig;
+
+    fn emulator(rom: &[u8], audio: Option<Beeper>) -> Emulator {
+        let mut chip8 = Chip8::default();
+        chip8.load_rom(rom.to_vec()).unwrap();
+        Emulator {
+            chip8,
+            clock: FrameClock::new(10),
+            crash: None,
+            settings: Settings::default(),
+            state_slot: 0,
+            state_slots: vec![None; STATE_SLOTS],
+            rewind: RewindBuffer::new(RewindConfig::default()),
+            rewinding: false,
+            debugger: Debugger::new(),
+            debug_commands: None,
+            audio,
+            video: None,
+            held_keys: [false; 16],
+        }
+    }
+
+    #[test]
+    fn sound_is_written_before_the_timers_tick() {
+        // ld v0, 1; ld st, v0; jp 204
+        let path = std::env::temp_dir().join(format!("miko_chip8_{}_emulator.wav", std::process::id()));
+        let config = AudioConfig { sample_rate: 6000, ..AudioConfig::default() };
+        let audio = Beeper::new(config, Box::new(WavSink::create(&path, 6000).unwrap()));
+        let mut emulator = emulator(&[0x60, 0x01, 0xF0, 0x18, 0x12, 0x04], Some(audio));
+        for _ in 0..4 {
+            assert!(emulator.frame());
+        }
+        drop(emulator); // finishes the sound output
+
+        let (_, samples) = read_wav(&std::fs::read(&path).unwrap()).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(samples.len(), 4 * 100);
+        // ST=1 beeps for exactly the frame that set it
+        let sounding: Vec<usize> = (0..samples.len()).filter(|&n| samples[n] != 0).collect();
+        assert_eq!(sounding.first(), Some(&0));
+        assert_eq!(sounding.last(), Some(&99));
+    }
+
+    #[test]
+    fn slots_fall_back_to_memory_without_a_file() {
+        // add v0, 1; jp 200
+        let mut emulator = emulator(&[0x70, 0x01, 0x12, 0x00], None);
+        emulator.settings.state_path = Some(std::env::temp_dir().join("miko_chip8_no_such_dir").join("rom"));
+        emulator.frame();
+        emulator.save_slot();
+        let saved = emulator.chip8.save_state();
+        emulator.frame();
+        emulator.load_slot();
+        assert_eq!(emulator.chip8.save_state(), saved);
+    }
+}
@@ -0,0 +1,96 @@
+use std::sync::{Arc, Mutex};
+use crate::chip8::Screen;
+
+// Frame is a finished picture of the emulator, as the window draws it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Frame {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+    pub overlay: Option<String>, // text drawn on top of the screen, the registers while paused
+    pub crashed: bool,
+}
+
+impl Frame {
+    pub fn screen(&self) -> Screen<'_> {
+        Screen { width: self.width, height: self.height, pixels: &self.pixels }
+    }
+}
+
+// double_buffer makes the two ends of a double buffer of frames: the emulator thread fills one
+// buffer while the window shows the other. A finished frame waits in a shared slot, the lock
+// is only held to swap buffers, so neither side waits for the other to draw. The newest
+// frame wins: one the window didn't take yet is replaced and its buffer filled again
+pub fn double_buffer() -> (FrameSender, FrameReceiver) {
+    let shared = Arc::new(Mutex::new(Shared::default()));
+    (FrameSender { shared: shared.clone(), back: Frame::default() }, FrameReceiver { shared, current: Frame::default() })
+}
+
+#[derive(Default)]
+struct Shared {
+    pending: Option<Frame>, // published, not taken by the window yet
+    free: Option<Frame>, // shown by the window before, can be filled again
+}
+
+// FrameSender is the emulator's end of the double buffer
+pub struct FrameSender {
+    shared: Arc<Mutex<Shared>>,
+    back: Frame, // filled by publish
+}
+
+impl FrameSender {
+    // publish fills a buffer with fill and hands it to the window, in place of the previous
+    // frame if the window hasn't taken that yet. returns false once the window is gone
+    pub fn publish(&mut self, fill: impl FnOnce(&mut Frame)) -> bool {
+        fill(&mut self.back);
+        let mut shared = self.shared.lock().expect("the frame slot is never left broken");
+        let replaced = shared.pending.replace(std::mem::take(&mut self.back));
+        self.back = replaced.or_else(|| shared.free.take()).unwrap_or_default();
+        Arc::strong_count(&self.shared) > 1
+    }
+}
+
+// FrameReceiver is the window's end of the double buffer
+pub struct FrameReceiver {
+    shared: Arc<Mutex<Shared>>,
+    current: Frame,
+}
+
+impl FrameReceiver {
+    // latest returns the newest frame, None once the emulator has stopped and its last
+    // frame was returned
+    pub fn latest(&mut self) -> Option<&Frame> {
+        let mut shared = self.shared.lock().expect("the frame slot is never left broken");
+        match shared.pending.take() {
+            Some(frame) => shared.free = Some(std::mem::replace(&mut self.current, frame)),
+            None if Arc::strong_count(&self.shared) == 1 => return None,
+            None => (),
+        }
+        drop(shared);
+        Some(&self.current)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hands_frames_over_without_waiting() {
+        let (mut sender, mut receiver) = double_buffer();
+        let fill = |value: u8| move |frame: &mut Frame| frame.pixels = vec![value];
+        assert!(sender.publish(fill(1)));
+        assert_eq!(receiver.latest().unwrap().pixels, [1]);
+        // the window didn't look in between, it shows the newer frame
+        assert!(sender.publish(fill(2)));
+        assert!(sender.publish(fill(3)));
+        assert_eq!(receiver.latest().unwrap().pixels, [3]);
+        assert_eq!(receiver.latest().unwrap().pixels, [3]);
+
+        // the last frame still arrives after the emulator stopped
+        assert!(sender.publish(fill(5)));
+        drop(sender);
+        assert_eq!(receiver.latest().unwrap().pixels, [5]);
+        assert_eq!(receiver.latest(), None);
+    }
+}
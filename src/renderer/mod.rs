@@ -2,34 +2,121 @@ use speedy2d::color::Color;
 use speedy2d::dimen::Vector2;
 use speedy2d::Graphics2D;
 use speedy2d::shape::Rectangle;
-use speedy2d::window::{KeyScancode, UserEventSender, VirtualKeyCode, WindowHandler, WindowHelper, WindowStartupInfo};
-use crate::chip8::Chip8;
+use speedy2d::window::{KeyScancode, UserEventSender, VirtualKeyCode, WindowHandler, WindowHelper};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Sender};
+use std::thread::JoinHandle;
+use crate::audio::Beeper;
+use crate::chip8::Screen;
+use crate::debugger::Debugger;
+use crate::movie::Session;
+use crate::keymap::Keymap;
+use crate::palette::Palette;
+use crate::rewind::RewindConfig;
+use crate::video::{Recorder, VideoConfig};
 
+mod emulator;
+mod frames;
+mod text;
+
+pub use emulator::{Control, Emulator};
+pub use frames::{double_buffer, Frame, FrameReceiver, FrameSender};
+
+pub const STATE_SLOTS: usize = 10; // F6/F7 cycle through slots 0 to 9
+
+// Settings are the parts of the window behaviour that can be chosen before it is opened
+#[derive(Debug, Clone, Default)]
+pub struct Settings {
+    pub palette: Palette, // colors of the pixel values
+    pub paused: bool, // start paused, P toggles
+    pub muted: bool, // don't play the sound timer, M toggles
+    pub state_path: Option<PathBuf>, // save states go to <state_path>.state<slot>, memory only if None
+    pub rewind: RewindConfig, // how much gameplay Backspace can rewind
+    pub max_catch_up: u32, // frames run back to back after a stall, see Scheduler
+    pub screenshot_scale: usize, // image pixels per chip8 pixel of F12 screenshots
+    pub video: VideoConfig, // how F2 records videos
+    pub keymap: Keymap, // host keys of the chip8 keypad
+    pub movie: Option<Session>, // movie being recorded or played, runs the emulator frame by frame
+}
+
+// Outputs are where the window sends its sound and video
+pub struct Outputs {
+    pub audio: Option<Beeper>, // None if there is no sound at all
+    pub video: Option<Recorder>, // recording since the start, like F2 does
+}
+
+// Renderer is the window: it draws the frames the emulator thread publishes and sends it
+// the keys. The emulator runs on its own thread, so slow drawing doesn't slow it down
 pub struct Renderer {
-    pub chip8: Chip8,
-    pub operations_per_second: u32,
-    pub last_frame_time: std::time::Instant,
-    pub last_time_tick_time: std::time::Instant,
-    pub last_instruction_time: std::time::Instant,
-    pub user_event_sender: UserEventSender<()>,
+    palette: Palette,
+    keymap: Keymap,
+    controls: Sender<Control>, // to the emulator thread
+    frames: FrameReceiver, // from the emulator thread
+    emulator: Option<JoinHandle<()>>, // joined when the window closes
+    host_keys_down: HashSet<VirtualKeyCode>, // held host keys that are bound to chip8 keys
 }
 
 impl Renderer {
-    fn draw_frame(chip8: &mut Chip8, helper: &mut WindowHelper, graphics: &mut Graphics2D) {
-        let frame = chip8.get_screen();
+    // new starts the emulator thread, it wakes the window up with user_event_sender whenever
+    // a frame is ready
+    pub fn new(emulator: Emulator, user_event_sender: UserEventSender<()>) -> Renderer {
+        let palette = emulator.settings.palette;
+        let keymap = emulator.settings.keymap.clone();
+        let (controls, control_receiver) = mpsc::channel();
+        let (frame_sender, frames) = double_buffer();
+        let thread = std::thread::Builder::new()
+            .name("emulator".to_string())
+            .spawn(move || emulator.run(control_receiver, frame_sender, user_event_sender))
+            .expect("cannot start the emulator thread");
+        Renderer {
+            palette,
+            keymap,
+            controls,
+            frames,
+            emulator: Some(thread),
+            host_keys_down: HashSet::new(),
+        }
+    }
+
+    // send hands a control to the emulator thread, which may have stopped already
+    fn send(&self, control: Control) {
+        let _ = self.controls.send(control);
+    }
+
+    // draw_overlay shows text on top of the screen, the registers while paused
+    fn draw_overlay(overlay: &str, crashed: bool, helper: &mut WindowHelper, graphics: &mut Graphics2D) {
+        let lines: Vec<&str> = overlay.lines().collect();
+        let window = helper.get_size_pixels();
+        let columns = lines.iter().map(|line| line.len()).max().unwrap_or(1);
+        let scale = (window.x as usize / ((columns + 2) * text::ADVANCE)).clamp(1, 4) as f32;
+        let margin = text::ADVANCE as f32 * scale;
+        let (width, height) = text::text_size(&lines, scale);
+        let panel = Rectangle::new(
+            Vector2::new(0.0, 0.0),
+            Vector2::new(width + 2.0 * margin, height + 2.0 * margin),
+        );
+        graphics.draw_rectangle(panel, Color::from_rgba(0.0, 0.0, 0.0, 0.75));
+        let color = if crashed { Color::RED } else { Color::YELLOW };
+        text::draw_text(graphics, &lines, margin, margin, scale, color);
+    }
+
+    fn draw_frame(frame: &Screen, palette: &Palette, helper: &mut WindowHelper, graphics: &mut Graphics2D) {
         let pixel_size: usize;
         {
+            // pixels are as big as they can be for the active resolution
             let screen_size = helper.get_size_pixels();
             let x = screen_size.x;
             let y = screen_size.y;
-            let max_pixel_size_x = (x / 64) as usize;
-            let max_pixel_size_y = (y / 32) as usize;
+            let max_pixel_size_x = x as usize / frame.width;
+            let max_pixel_size_y = y as usize / frame.height;
             pixel_size = max_pixel_size_x.min(max_pixel_size_y);
         }
-        graphics.clear_screen(Color::BLACK);
-        for y in 0..32 {
-            for x in 0..64 {
-                if frame[y * 64 + x] == 0 {
+        graphics.clear_screen(Color::from_hex_rgb(palette.color(0)));
+        for y in 0..frame.height {
+            for x in 0..frame.width {
+                let pixel = frame.pixel(x, y);
+                if pixel == 0 {
                     continue
                 }
                 let rect = Rectangle::new(
@@ -42,51 +129,29 @@ impl Renderer {
                         ((y + 1) * pixel_size) as f32
                     )
                 );
-                graphics.draw_rectangle(rect, Color::WHITE);
+                graphics.draw_rectangle(rect, Color::from_hex_rgb(palette.color(pixel)));
             }
         }
     }
 }
 
 impl WindowHandler for Renderer {
-    fn on_start(&mut self, _helper: &mut WindowHelper<()>, _info: WindowStartupInfo) {
-        let user_event_sender = self.user_event_sender.clone();
-        std::thread::spawn(move || {
-            loop {
-                user_event_sender
-                    .send_event(())
-                    .unwrap();
-                std::thread::sleep(std::time::Duration::from_secs(1) / 60);
-            }
-        });
-    }
-
     fn on_user_event(&mut self, helper: &mut WindowHelper<()>, _user_event: ()) {
         helper.request_redraw();
     }
 
     fn on_draw(&mut self, helper: &mut WindowHelper, graphics: &mut Graphics2D) {
-        let now = std::time::Instant::now();
-        let time_since_last_frame = now.duration_since(self.last_frame_time);
-        let time_since_last_instruction = now.duration_since(self.last_instruction_time);
-        let time_since_last_time_tick = now.duration_since(self.last_time_tick_time);
-        let time_per_frame = std::time::Duration::from_secs(1) / 60;
-        let time_per_instruction =
-            std::time::Duration::from_secs(1) / self.operations_per_second;
-        let time_per_time_tick = std::time::Duration::from_secs(1) / 60;
-        if time_since_last_frame >= time_per_frame {
-            self.last_frame_time = now;
-            Renderer::draw_frame(&mut self.chip8, helper, graphics);
-        }
-
-        if time_since_last_instruction >= time_per_instruction {
-            self.last_instruction_time = now;
-            self.chip8.next_instruction();
+        // the emulator thread stops when the program exits or the debugger quits
+        let Some(frame) = self.frames.latest() else {
+            helper.terminate_loop();
+            return;
+        };
+        if frame.pixels.is_empty() {
+            return; // nothing was published yet
         }
-
-        if time_since_last_time_tick >= time_per_time_tick {
-            self.last_time_tick_time = now;
-            self.chip8.timer_tick();
+        Renderer::draw_frame(&frame.screen(), &self.palette, helper, graphics);
+        if let Some(overlay) = &frame.overlay {
+            Renderer::draw_overlay(overlay, frame.crashed, helper, graphics);
         }
     }
 
@@ -100,26 +165,31 @@ impl WindowHandler for Renderer {
         } else {
             return;
         }
-        match key_code {
-            VirtualKeyCode::Key1 => self.chip8.keyboard[0x1] = true,
-            VirtualKeyCode::Key2 => self.chip8.keyboard[0x2] = true,
-            VirtualKeyCode::Key3 => self.chip8.keyboard[0x3] = true,
-            VirtualKeyCode::Key4 => self.chip8.keyboard[0xC] = true,
-            VirtualKeyCode::Q    => self.chip8.keyboard[0x4] = true,
-            VirtualKeyCode::W    => self.chip8.keyboard[0x5] = true,
-            VirtualKeyCode::E    => self.chip8.keyboard[0x6] = true,
-            VirtualKeyCode::R    => self.chip8.keyboard[0xD] = true,
-            VirtualKeyCode::A    => self.chip8.keyboard[0x7] = true,
-            VirtualKeyCode::S    => self.chip8.keyboard[0x8] = true,
-            VirtualKeyCode::D    => self.chip8.keyboard[0x9] = true,
-            VirtualKeyCode::F    => self.chip8.keyboard[0xE] = true,
-            VirtualKeyCode::Z    => self.chip8.keyboard[0xA] = true,
-            VirtualKeyCode::X    => self.chip8.keyboard[0x0] = true,
-            VirtualKeyCode::C    => self.chip8.keyboard[0xB] = true,
-            VirtualKeyCode::V    => self.chip8.keyboard[0xF] = true,
-            _ => ()
+        // keypad keys come first, so the keymap can take over a hotkey
+        if let Some(key) = self.keymap.key(key_code) {
+            self.host_keys_down.insert(key_code);
+            self.send(Control::Key(key, true));
+            return;
         }
+        let control = match key_code {
+            VirtualKeyCode::P    => Control::TogglePause,
+            VirtualKeyCode::M    => Control::ToggleMute,
+            VirtualKeyCode::F8   => Control::DebugStep(Debugger::step_out),
+            VirtualKeyCode::F10  => Control::DebugStep(Debugger::step_over),
+            VirtualKeyCode::F11  => Control::DebugStep(Debugger::step),
+            VirtualKeyCode::F5   => Control::SaveSlot,
+            VirtualKeyCode::F6   => Control::SelectSlot(-1),
+            VirtualKeyCode::F7   => Control::SelectSlot(1),
+            VirtualKeyCode::F9   => Control::LoadSlot,
+            VirtualKeyCode::F2   => Control::ToggleVideo,
+            VirtualKeyCode::F12  => Control::Screenshot,
+            // held while running rewinds continuously, while paused it steps back one frame
+            VirtualKeyCode::Backspace => Control::Rewind(true),
+            _ => return,
+        };
+        self.send(control);
     }
+
     fn on_key_up(&mut self,
                  _helper: &mut WindowHelper<()>,
                  virtual_key_code: Option<VirtualKeyCode>,
@@ -130,24 +200,27 @@ impl WindowHandler for Renderer {
         } else {
             return;
         }
-        match key_code {
-            VirtualKeyCode::Key1 => self.chip8.keyboard[0x1] = false,
-            VirtualKeyCode::Key2 => self.chip8.keyboard[0x2] = false,
-            VirtualKeyCode::Key3 => self.chip8.keyboard[0x3] = false,
-            VirtualKeyCode::Key4 => self.chip8.keyboard[0xC] = false,
-            VirtualKeyCode::Q => self.chip8.keyboard[0x4] = false,
-            VirtualKeyCode::W => self.chip8.keyboard[0x5] = false,
-            VirtualKeyCode::E => self.chip8.keyboard[0x6] = false,
-            VirtualKeyCode::R => self.chip8.keyboard[0xD] = false,
-            VirtualKeyCode::A => self.chip8.keyboard[0x7] = false,
-            VirtualKeyCode::S => self.chip8.keyboard[0x8] = false,
-            VirtualKeyCode::D => self.chip8.keyboard[0x9] = false,
-            VirtualKeyCode::F => self.chip8.keyboard[0xE] = false,
-            VirtualKeyCode::Z => self.chip8.keyboard[0xA] = false,
-            VirtualKeyCode::X => self.chip8.keyboard[0x0] = false,
-            VirtualKeyCode::C => self.chip8.keyboard[0xB] = false,
-            VirtualKeyCode::V => self.chip8.keyboard[0xF] = false,
-            _ => ()
+        // a chip8 key stays pressed while any of its host keys is held
+        if let Some(key) = self.keymap.key(key_code) {
+            self.host_keys_down.remove(&key_code);
+            let held = self.keymap.host_keys(key).iter().any(|host_key| self.host_keys_down.contains(host_key));
+            self.send(Control::Key(key, held));
+            return;
+        }
+        if key_code == VirtualKeyCode::Backspace {
+            self.send(Control::Rewind(false));
         }
     }
-}
\ No newline at end of file
+}
+
+impl Drop for Renderer {
+    // the emulator thread saves the movie and finishes the sound and video before it ends
+    fn drop(&mut self) {
+        self.send(Control::Quit);
+        if let Some(thread) = self.emulator.take() {
+            if thread.join().is_err() {
+                eprintln!("the emulator thread panicked");
+            }
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use speedy2d::color::Color;
+use speedy2d::dimen::Vector2;
+use speedy2d::Graphics2D;
+use speedy2d::shape::Rectangle;
+
+pub const GLYPH_WIDTH: usize = 3;
+pub const GLYPH_HEIGHT: usize = 5;
+pub const ADVANCE: usize = GLYPH_WIDTH + 1; // one pixel between characters
+pub const LINE_HEIGHT: usize = GLYPH_HEIGHT + 1; // one pixel between lines
+
+// glyph returns the 3x5 bitmap of a character, every row uses the low 3 bits, left pixel first
+fn glyph(c: char) -> [u8; GLYPH_HEIGHT] {
+    match c.to_ascii_uppercase() {
+        '0' => [7, 5, 5, 5, 7],
+        '1' => [2, 6, 2, 2, 7],
+        '2' => [7, 1, 7, 4, 7],
+        '3' => [7, 1, 7, 1, 7],
+        '4' => [5, 5, 7, 1, 1],
+        '5' => [7, 4, 7, 1, 7],
+        '6' => [7, 4, 7, 5, 7],
+        '7' => [7, 1, 1, 1, 1],
+        '8' => [7, 5, 7, 5, 7],
+        '9' => [7, 5, 7, 1, 7],
+        'A' => [2, 5, 7, 5, 5],
+        'B' => [6, 5, 6, 5, 6],
+        'C' => [3, 4, 4, 4, 3],
+        'D' => [6, 5, 5, 5, 6],
+        'E' => [7, 4, 6, 4, 7],
+        'F' => [7, 4, 6, 4, 4],
+        'G' => [3, 4, 5, 5, 3],
+        'H' => [5, 5, 7, 5, 5],
+        'I' => [7, 2, 2, 2, 7],
+        'J' => [1, 1, 1, 5, 2],
+        'K' => [5, 5, 6, 5, 5],
+        'L' => [4, 4, 4, 4, 7],
+        'M' => [5, 7, 7, 5, 5],
+        'N' => [6, 5, 5, 5, 5],
+        'O' => [2, 5, 5, 5, 2],
+        'P' => [6, 5, 6, 4, 4],
+        'Q' => [2, 5, 5, 6, 3],
+        'R' => [6, 5, 6, 5, 5],
+        'S' => [3, 4, 2, 1, 6],
+        'T' => [7, 2, 2, 2, 2],
+        'U' => [5, 5, 5, 5, 7],
+        'V' => [5, 5, 5, 5, 2],
+        'W' => [5, 5, 7, 7, 5],
+        'X' => [5, 5, 2, 5, 5],
+        'Y' => [5, 5, 2, 2, 2],
+        'Z' => [7, 1, 2, 4, 7],
+        ':' => [0, 2, 0, 2, 0],
+        '.' => [0, 0, 0, 0, 2],
+        ',' => [0, 0, 0, 2, 4],
+        '-' => [0, 0, 7, 0, 0],
+        '=' => [0, 7, 0, 7, 0],
+        '>' => [4, 2, 1, 2, 4],
+        '<' => [1, 2, 4, 2, 1],
+        '[' => [6, 4, 4, 4, 6],
+        ']' => [3, 1, 1, 1, 3],
+        ' ' => [0, 0, 0, 0, 0],
+        _ => [7, 1, 2, 0, 2], // ?
+    }
+}
+
+// text_size returns the width and height of lines drawn with pixels of size scale
+pub fn text_size(lines: &[&str], scale: f32) -> (f32, f32) {
+    let columns = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0);
+    (columns as f32 * ADVANCE as f32 * scale, lines.len() as f32 * LINE_HEIGHT as f32 * scale)
+}
+
+// draw_text draws lines of text with the top left corner at (x, y)
+pub fn draw_text(graphics: &mut Graphics2D, lines: &[&str], x: f32, y: f32, scale: f32, color: Color) {
+    for (row, line) in lines.iter().enumerate() {
+        for (column, c) in line.chars().enumerate() {
+            let left = x + (column * ADVANCE) as f32 * scale;
+            let top = y + (row * LINE_HEIGHT) as f32 * scale;
+            for (dy, bits) in glyph(c).iter().enumerate() {
+                for dx in 0..GLYPH_WIDTH {
+                    if bits >> (GLYPH_WIDTH - 1 - dx) & 1 == 0 {
+                        continue;
+                    }
+                    let pixel = Rectangle::new(
+                        Vector2::new(left + dx as f32 * scale, top + dy as f32 * scale),
+                        Vector2::new(left + (dx + 1) as f32 * scale, top + (dy + 1) as f32 * scale),
+                    );
+                    graphics.draw_rectangle(pixel, color);
+                }
+            }
+        }
+    }
+}
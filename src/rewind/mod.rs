@@ -0,0 +1,226 @@
+use std::collections::VecDeque;
+
+// MIN_SKIP is the shortest run of unchanged bytes that ends a literal run of a delta
+const MIN_SKIP: usize = 4;
+
+// RewindConfig limits how far back the buffer can go
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RewindConfig {
+    pub max_frames: usize, // rewind length, 60 frames is one second
+    pub memory_budget: usize, // bytes the recorded frames may take, the oldest are dropped first
+}
+
+impl Default for RewindConfig {
+    fn default() -> Self {
+        RewindConfig {
+            max_frames: 60 * 30,
+            memory_budget: 16 * 1024 * 1024,
+        }
+    }
+}
+
+// Step is what is needed to get from a recorded state to the one before it
+enum Step {
+    Delta(Vec<u8>), // previous state xor this one, run-length encoded
+    Full(Vec<u8>), // previous state as is, used when the sizes differ (quirks changed by load_state)
+}
+
+impl Step {
+    fn size(&self) -> usize {
+        match self {
+            Step::Delta(delta) => delta.len(),
+            Step::Full(state) => state.len(),
+        }
+    }
+}
+
+// RewindBuffer records one save state per frame. Only the newest state is kept whole,
+// every older one is stored as its difference to the next, which is tiny because
+// most of memory doesn't change from one frame to another
+pub struct RewindBuffer {
+    config: RewindConfig,
+    newest: Option<Vec<u8>>,
+    restored: bool, // pop returned the newest state, the emulator is at it
+    steps: VecDeque<Step>, // oldest first
+    bytes_used: usize, // size of the steps, the newest state is not counted
+}
+
+impl RewindBuffer {
+    pub fn new(config: RewindConfig) -> RewindBuffer {
+        RewindBuffer {
+            config,
+            newest: None,
+            restored: false,
+            steps: VecDeque::new(),
+            bytes_used: 0,
+        }
+    }
+
+    pub fn config(&self) -> RewindConfig {
+        self.config
+    }
+
+    // len returns how many frames back the buffer can go
+    pub fn len(&self) -> usize {
+        self.steps.len() + (self.newest.is_some() && !self.restored) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // bytes_used returns the memory taken by the recorded frames
+    pub fn bytes_used(&self) -> usize {
+        self.bytes_used
+    }
+
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.restored = false;
+        self.steps.clear();
+        self.bytes_used = 0;
+    }
+
+    // push records the state of a new frame
+    pub fn push(&mut self, state: Vec<u8>) {
+        if let Some(previous) = self.newest.take() {
+            let step = if previous.len() == state.len() {
+                Step::Delta(encode_delta(&previous, &state))
+            } else {
+                Step::Full(previous)
+            };
+            self.bytes_used += step.size();
+            self.steps.push_back(step);
+        }
+        self.newest = Some(state);
+        self.restored = false;
+
+        while self.len() > self.config.max_frames || self.bytes_used > self.config.memory_budget {
+            match self.steps.pop_front() {
+                Some(step) => self.bytes_used -= step.size(),
+                None => break,
+            }
+        }
+    }
+
+    // pop goes one frame back and returns the state of that frame, which becomes the newest one.
+    // the emulator is past the newest state while it runs, so that one is returned first
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        if !self.restored {
+            self.restored = true;
+            return self.newest.clone();
+        }
+        let step = self.steps.pop_back()?;
+        self.bytes_used -= step.size();
+        let newest = self.newest.take()?;
+        let previous = match step {
+            Step::Delta(delta) => apply_delta(newest, &delta),
+            Step::Full(state) => state,
+        };
+        self.newest = Some(previous.clone());
+        Some(previous)
+    }
+}
+
+// encode_delta xors two equally long states and run-length encodes the result as
+// (zero bytes to skip, number of literal bytes, literal bytes) triples, counts are varints
+fn encode_delta(previous: &[u8], current: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < current.len() {
+        let zeros_start = i;
+        while i < current.len() && previous[i] == current[i] {
+            i += 1;
+        }
+        let literal_start = i;
+        // short equal runs inside a changed area are cheaper as literals
+        while i < current.len() && (previous[i] != current[i] || equal_run(previous, current, i) < MIN_SKIP) {
+            i += 1;
+        }
+        write_varint(&mut out, literal_start - zeros_start);
+        write_varint(&mut out, i - literal_start);
+        out.extend(previous[literal_start..i].iter().zip(&current[literal_start..i]).map(|(a, b)| a ^ b));
+    }
+    out
+}
+
+// equal_run counts how many bytes from i on are the same in both states, up to MIN_SKIP
+fn equal_run(previous: &[u8], current: &[u8], i: usize) -> usize {
+    previous[i..].iter().zip(&current[i..]).take(MIN_SKIP).take_while(|(a, b)| a == b).count()
+}
+
+fn apply_delta(mut state: Vec<u8>, delta: &[u8]) -> Vec<u8> {
+    let mut position = 0;
+    let mut i = 0;
+    while i < delta.len() {
+        let zeros = read_varint(delta, &mut i);
+        let literals = read_varint(delta, &mut i);
+        position += zeros;
+        for byte in &delta[i..i + literals] {
+            state[position] ^= byte;
+            position += 1;
+        }
+        i += literals;
+    }
+    state
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(data: &[u8], i: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*i];
+        *i += 1;
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(n: u8) -> Vec<u8> {
+        let mut state = vec![0; 4096];
+        state[10] = n;
+        state[2000..2010].copy_from_slice(&[n; 10]);
+        state
+    }
+
+    #[test]
+    fn rewinds_frame_by_frame() {
+        let mut buffer = RewindBuffer::new(RewindConfig::default());
+        for n in 0..5 {
+            buffer.push(frame(n));
+        }
+        assert_eq!(buffer.len(), 5);
+        assert!(buffer.bytes_used() < 4 * 64);
+        for n in (0..5).rev() {
+            assert_eq!(buffer.pop(), Some(frame(n)));
+        }
+        assert_eq!(buffer.pop(), None);
+    }
+
+    #[test]
+    fn oldest_frames_are_dropped() {
+        let mut buffer = RewindBuffer::new(RewindConfig { max_frames: 3, memory_budget: 1 << 20 });
+        for n in 0..10 {
+            buffer.push(frame(n));
+        }
+        assert_eq!(buffer.len(), 3);
+        buffer.push(vec![1, 2, 3]);
+        assert_eq!(buffer.pop(), Some(vec![1, 2, 3]));
+        assert_eq!(buffer.pop(), Some(frame(9)));
+    }
+}
@@ -0,0 +1,83 @@
+use std::time::{Duration, Instant};
+use crate::machine::FRAMES_PER_SECOND;
+
+pub const DEFAULT_MAX_CATCH_UP: u32 = 6; // a tenth of a second
+
+// Scheduler tells a real time loop when to run its 60Hz frames. Frame n is due at
+// start + n / 60 seconds, computed from the start so the frame rate doesn't drift.
+// after a stall the frames that were missed are run back to back to catch up, at most
+// max_catch_up of them, the older ones are dropped and the game continues a bit later
+#[derive(Debug, Clone)]
+pub struct Scheduler {
+    start: Instant,
+    frames: u64, // frames run or dropped since start
+    dropped: u64,
+    max_catch_up: u32,
+}
+
+impl Scheduler {
+    // new makes the first frame due at start
+    pub fn new(start: Instant, max_catch_up: u32) -> Scheduler {
+        Scheduler { start, frames: 0, dropped: 0, max_catch_up }
+    }
+
+    // frame_time returns when frame n is due
+    fn frame_time(&self, frame: u64) -> Instant {
+        self.start + Duration::from_nanos(frame * 1_000_000_000 / FRAMES_PER_SECOND as u64)
+    }
+
+    // due returns how many frames to run now: the frame whose time has come, and up to
+    // max_catch_up frames that are late
+    pub fn due(&mut self, now: Instant) -> u32 {
+        if now < self.frame_time(self.frames) {
+            return 0;
+        }
+        // frame n is due once n * 10^9 / 60, rounded down, is at most the elapsed nanoseconds
+        let elapsed = now.duration_since(self.start).as_nanos();
+        let due = ((elapsed + 1) * FRAMES_PER_SECOND as u128).div_ceil(1_000_000_000) as u64;
+        let behind = due - self.frames;
+        let run = behind.min(self.max_catch_up as u64 + 1);
+        self.dropped += behind - run;
+        self.frames += behind;
+        run as u32
+    }
+
+    // next_frame returns when the next frame is due
+    pub fn next_frame(&self) -> Instant {
+        self.frame_time(self.frames)
+    }
+
+    // dropped returns how many frames were skipped because they were too late
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn catches_up_and_drops_late_frames() {
+        let start = Instant::now();
+        let at = |millis: u64| start + Duration::from_millis(millis);
+        let mut scheduler = Scheduler::new(start, 2);
+        assert_eq!(scheduler.due(at(0)), 1);
+        assert_eq!(scheduler.due(at(10)), 0);
+        assert_eq!(scheduler.due(at(17)), 1);
+        assert_eq!(scheduler.next_frame(), start + Duration::from_nanos(33_333_333));
+        // the frames of 33ms and 50ms are both run
+        assert_eq!(scheduler.due(at(60)), 2);
+        assert_eq!(scheduler.dropped(), 0);
+        // a second long stall: 3 frames run, the other 57 are dropped
+        assert_eq!(scheduler.due(at(1060)), 3);
+        assert_eq!(scheduler.dropped(), 57);
+        assert_eq!(scheduler.due(at(1066)), 0);
+
+        // a minute later the frames are still on time to the nanosecond
+        let mut scheduler = Scheduler::new(start, 0);
+        let frames: u32 = (0..3600).map(|frame| scheduler.due(scheduler.frame_time(frame))).sum();
+        assert_eq!(frames, 3600);
+        assert_eq!(scheduler.next_frame(), start + Duration::from_secs(60));
+    }
+}
@@ -0,0 +1,335 @@
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use crate::chip8::{Chip8Error, Instruction, Register, StepOutcome, TraceEntry, TraceSink};
+
+// version 1 had no waiting for vblank outcome, it can still be read
+const MAGIC: &[u8; 4] = b"C8TR";
+const VERSION: u8 = 2;
+
+// TraceFormat is how a FileTracer writes the entries
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TraceFormat {
+    #[default]
+    Text, // one line per instruction, see TraceEntry's Display
+    Binary, // a few bytes per instruction, read back with decode_binary
+}
+
+impl TraceFormat {
+    pub fn from_name(name: &str) -> Option<TraceFormat> {
+        match name.to_ascii_lowercase().as_str() {
+            "text" | "txt" => Some(TraceFormat::Text),
+            "binary" | "bin" => Some(TraceFormat::Binary),
+            _ => None,
+        }
+    }
+}
+
+// TraceConfig says where and what to trace
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceConfig {
+    pub path: PathBuf,
+    pub format: TraceFormat,
+    pub ranges: Vec<RangeInclusive<u16>>, // only instructions at these addresses, all if empty
+    pub max_bytes: Option<u64>, // size after which the file is rotated
+    pub backups: usize, // rotated files that are kept as <path>.1 (newest) to <path>.N
+}
+
+impl TraceConfig {
+    pub fn new(path: PathBuf) -> TraceConfig {
+        TraceConfig {
+            path,
+            format: TraceFormat::Text,
+            ranges: Vec::new(),
+            max_bytes: None,
+            backups: 1,
+        }
+    }
+
+    fn wants(&self, pc: u16) -> bool {
+        self.ranges.is_empty() || self.ranges.iter().any(|range| range.contains(&pc))
+    }
+}
+
+// FileTracer is a TraceSink that writes to a file. Write errors stop the tracing,
+// the first one is kept and returned by finish()
+pub struct FileTracer {
+    config: TraceConfig,
+    out: Option<BufWriter<File>>,
+    written: u64, // bytes in the current file
+    error: Option<io::Error>,
+    buffer: Vec<u8>,
+}
+
+impl FileTracer {
+    // create truncates the trace file, or creates it
+    pub fn create(config: TraceConfig) -> io::Result<FileTracer> {
+        let mut tracer = FileTracer { config, out: None, written: 0, error: None, buffer: Vec::new() };
+        tracer.open()?;
+        Ok(tracer)
+    }
+
+    fn open(&mut self) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(&self.config.path)?);
+        self.written = 0;
+        if self.config.format == TraceFormat::Binary {
+            out.write_all(MAGIC)?;
+            out.write_all(&[VERSION])?;
+            self.written = MAGIC.len() as u64 + 1;
+        }
+        self.out = Some(out);
+        Ok(())
+    }
+
+    // rotate moves the full file to <path>.1, older backups move one number up
+    fn rotate(&mut self) -> io::Result<()> {
+        if let Some(mut out) = self.out.take() {
+            out.flush()?;
+        }
+        if self.config.backups > 0 {
+            for n in (1..self.config.backups).rev() {
+                let from = backup_path(&self.config.path, n);
+                if from.exists() {
+                    fs::rename(&from, backup_path(&self.config.path, n + 1))?;
+                }
+            }
+            fs::rename(&self.config.path, backup_path(&self.config.path, 1))?;
+        }
+        self.open()
+    }
+
+    fn write(&mut self, entry: &TraceEntry) -> io::Result<()> {
+        self.buffer.clear();
+        match self.config.format {
+            TraceFormat::Text => writeln!(self.buffer, "{}", entry)?,
+            TraceFormat::Binary => encode_binary(entry, &mut self.buffer),
+        }
+        let len = self.buffer.len() as u64;
+        if self.config.max_bytes.is_some_and(|max| self.written + len > max && self.written > header_len(self.config.format)) {
+            self.rotate()?;
+        }
+        if let Some(out) = &mut self.out {
+            out.write_all(&self.buffer)?;
+            self.written += len;
+        }
+        Ok(())
+    }
+
+    // finish flushes the file and returns the first error that stopped the tracing
+    pub fn finish(mut self) -> io::Result<()> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        match self.out.take() {
+            Some(mut out) => out.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl TraceSink for FileTracer {
+    fn record(&mut self, entry: &TraceEntry) {
+        if self.out.is_none() || !self.config.wants(entry.pc) {
+            return;
+        }
+        if let Err(err) = self.write(entry) {
+            self.out = None;
+            self.error = Some(err);
+        }
+    }
+
+    fn flush(&mut self) {
+        if let Some(Err(err)) = self.out.as_mut().map(BufWriter::flush) {
+            self.out = None;
+            self.error = Some(err);
+        }
+    }
+}
+
+fn header_len(format: TraceFormat) -> u64 {
+    match format {
+        TraceFormat::Text => 0,
+        TraceFormat::Binary => MAGIC.len() as u64 + 1,
+    }
+}
+
+fn backup_path(path: &Path, n: usize) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+// register ids of the binary format: 0 to 15 are V0 to VF
+fn register_id(register: Register) -> u8 {
+    match register {
+        Register::V(x) => x,
+        Register::I => 16,
+        Register::Delay => 17,
+        Register::Sound => 18,
+        Register::Sp => 19,
+    }
+}
+
+// encode_binary appends an entry: pc, opcode, the address after F000, the outcome,
+// then the number of changes and (register id, value) for each, I takes 2 bytes.
+// numbers are big endian
+pub fn encode_binary(entry: &TraceEntry, out: &mut Vec<u8>) {
+    out.extend(entry.pc.to_be_bytes());
+    out.extend(entry.opcode.to_be_bytes());
+    if Instruction::decode(entry.opcode) == Some(Instruction::LongIndex(0)) {
+        // the address is missing if F000 was the last word of memory
+        let address = match entry.instruction {
+            Some(Instruction::LongIndex(address)) => address,
+            _ => 0,
+        };
+        out.extend(address.to_be_bytes());
+    }
+    match &entry.outcome {
+        Ok(StepOutcome::Executed) => out.push(0),
+        Ok(StepOutcome::WaitingForKey) => out.push(1),
+        Ok(StepOutcome::Exited) => out.push(2),
+        Err(Chip8Error::StackUnderflow { .. }) => out.push(3),
+        Err(Chip8Error::StackOverflow { .. }) => out.push(4),
+        Err(Chip8Error::MemoryOutOfBounds { address, .. }) => {
+            out.push(5);
+            out.extend((*address as u32).to_be_bytes());
+        },
+        Err(Chip8Error::InvalidOpcode { .. }) => out.push(6),
+        Err(Chip8Error::PcOutOfRange { .. }) => out.push(7),
+        Ok(StepOutcome::WaitingForVblank) => out.push(8),
+    }
+    out.push(entry.changes.len() as u8);
+    for &(register, value) in &entry.changes {
+        out.push(register_id(register));
+        if register == Register::I {
+            out.extend(value.to_be_bytes());
+        } else {
+            out.push(value as u8);
+        }
+    }
+}
+
+// decode_binary reads back a whole file written in the binary format
+pub fn decode_binary(data: &[u8]) -> Result<Vec<TraceEntry>, &'static str> {
+    let body = data.strip_prefix(MAGIC).ok_or("not a binary trace")?;
+    let (&version, mut body) = body.split_first().ok_or("not a binary trace")?;
+    if !(1..=VERSION).contains(&version) {
+        return Err("unsupported trace version");
+    }
+    let mut entries = Vec::new();
+    while !body.is_empty() {
+        entries.push(decode_entry(&mut body, version).ok_or("truncated trace")?);
+    }
+    Ok(entries)
+}
+
+fn take_u8(data: &mut &[u8]) -> Option<u8> {
+    let (&first, rest) = data.split_first()?;
+    *data = rest;
+    Some(first)
+}
+
+fn take_u16(data: &mut &[u8]) -> Option<u16> {
+    Some(u16::from_be_bytes([take_u8(data)?, take_u8(data)?]))
+}
+
+fn decode_entry(data: &mut &[u8], version: u8) -> Option<TraceEntry> {
+    let pc = take_u16(data)?;
+    let opcode = take_u16(data)?;
+    let instruction = match Instruction::decode(opcode) {
+        Some(Instruction::LongIndex(_)) => Some(Instruction::LongIndex(take_u16(data)?)),
+        instruction => instruction,
+    };
+    let outcome = match take_u8(data)? {
+        0 => Ok(StepOutcome::Executed),
+        1 => Ok(StepOutcome::WaitingForKey),
+        2 => Ok(StepOutcome::Exited),
+        3 => Err(Chip8Error::StackUnderflow { pc, opcode }),
+        4 => Err(Chip8Error::StackOverflow { pc, opcode }),
+        5 => {
+            let address = ((take_u16(data)? as usize) << 16) | take_u16(data)? as usize;
+            Err(Chip8Error::MemoryOutOfBounds { pc, opcode, address })
+        },
+        6 => Err(Chip8Error::InvalidOpcode { pc, opcode }),
+        7 => Err(Chip8Error::PcOutOfRange { pc }),
+        8 if version >= 2 => Ok(StepOutcome::WaitingForVblank),
+        _ => return None,
+    };
+    let count = take_u8(data)?;
+    let mut changes = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let change = match take_u8(data)? {
+            x @ 0..=15 => (Register::V(x), take_u8(data)? as u16),
+            16 => (Register::I, take_u16(data)?),
+            17 => (Register::Delay, take_u8(data)? as u16),
+            18 => (Register::Sound, take_u8(data)? as u16),
+            19 => (Register::Sp, take_u8(data)? as u16),
+            _ => return None,
+        };
+        changes.push(change);
+    }
+    Some(TraceEntry { pc, opcode, instruction, changes, outcome })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::Chip8;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("miko_chip8_{}_{}", std::process::id(), name))
+    }
+
+    // 200: ld v0, 5; 202: ld i, 0x300; 204: call 208; 206: jp 206; 208: ret
+    const ROM: [u8; 10] = [0x60, 0x05, 0xA3, 0x00, 0x22, 0x08, 0x12, 0x06, 0x00, 0xEE];
+
+    fn trace(config: TraceConfig, steps: usize) {
+        let mut chip8 = Chip8::default();
+        chip8.load_rom(ROM.to_vec()).unwrap();
+        chip8.set_trace_sink(Some(Box::new(FileTracer::create(config).unwrap())));
+        for _ in 0..steps {
+            chip8.next_instruction().unwrap();
+        }
+    }
+
+    #[test]
+    fn writes_text_and_binary() {
+        let path = temp_path("trace.txt");
+        trace(TraceConfig::new(path.clone()), 3);
+        let text = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "0200  6005  LD V0, 0x05              V0=05");
+        assert_eq!(lines[2], "0204  2208  CALL 0x208               SP=01");
+
+        let path = temp_path("trace.bin");
+        trace(TraceConfig { format: TraceFormat::Binary, ranges: vec![0x202..=0x204], ..TraceConfig::new(path.clone()) }, 3);
+        let entries = decode_binary(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].changes, [(Register::I, 0x300)]);
+        assert_eq!(entries[1].instruction, Some(Instruction::Call(0x208)));
+        // version 1 files are the same without the vblank outcome
+        let mut old = fs::read(&path).unwrap();
+        old[MAGIC.len()] = 1;
+        assert_eq!(decode_binary(&old).unwrap(), entries);
+        let _ = fs::remove_file(temp_path("trace.txt"));
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn rotates_full_files() {
+        let path = temp_path("rotated.txt");
+        let config = TraceConfig { max_bytes: Some(100), backups: 2, ..TraceConfig::new(path.clone()) };
+        trace(config, 10);
+        let sizes: Vec<u64> = [path.clone(), backup_path(&path, 1), backup_path(&path, 2)].iter()
+            .map(|path| fs::metadata(path).unwrap().len())
+            .collect();
+        assert!(sizes.iter().all(|&size| size > 0 && size <= 100), "{:?}", sizes);
+        assert!(!backup_path(&path, 3).exists());
+        for n in 1..=2 {
+            let _ = fs::remove_file(backup_path(&path, n));
+        }
+        let _ = fs::remove_file(path);
+    }
+}
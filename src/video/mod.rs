@@ -0,0 +1,202 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use crate::chip8::Screen;
+use crate::image::{gif, y4m, Image};
+use crate::palette::Palette;
+
+// VideoFormat is a file format a Recorder can write
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoFormat {
+    Gif, // animated, loops forever
+    Y4m, // uncompressed YUV4MPEG2 for ffmpeg
+}
+
+impl VideoFormat {
+    // from_path picks the format by the extension of path
+    pub fn from_path(path: &Path) -> Option<VideoFormat> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "gif" => Some(VideoFormat::Gif),
+            "y4m" => Some(VideoFormat::Y4m),
+            _ => None,
+        }
+    }
+
+    // default_frame_skip is 2 for GIF: most viewers slow down GIFs with frames shorter than
+    // 2 hundredths of a second, and 60Hz frames are 1.7
+    pub fn default_frame_skip(self) -> u32 {
+        match self {
+            VideoFormat::Gif => 2,
+            VideoFormat::Y4m => 1,
+        }
+    }
+}
+
+// VideoConfig is how a recording looks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VideoConfig {
+    pub scale: usize, // image pixels per chip8 pixel of the first frame
+    pub frame_skip: Option<u32>, // keep every nth 60Hz frame, the format's default if None
+    pub palette: Palette,
+}
+
+impl Default for VideoConfig {
+    fn default() -> Self {
+        VideoConfig { scale: 4, frame_skip: None, palette: Palette::default() }
+    }
+}
+
+// Recorder writes every frame_skip-th 60Hz frame it is given to a video file. The size
+// of the video is set by the first frame, later frames of another resolution are stretched
+// to it. Write errors stop the recording, the first one is kept and returned by finish()
+pub struct Recorder {
+    format: VideoFormat,
+    config: VideoConfig,
+    frame_skip: u32,
+    out: Option<BufWriter<File>>,
+    size: Option<(usize, usize)>, // of the video, once the first frame was written
+    frames: u64, // frames given to frame()
+    written: u64, // frames in the file
+    pending: Option<(Image, u64)>, // GIF frame that is waiting for its length, and the frame it started at
+    error: Option<io::Error>,
+}
+
+impl Recorder {
+    // create truncates the video file, or creates it, the format is picked by the extension
+    pub fn create(path: &Path, config: VideoConfig) -> io::Result<Recorder> {
+        let format = VideoFormat::from_path(path).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "unknown video format, use .gif or .y4m")
+        })?;
+        let out = BufWriter::new(File::create(path)?);
+        Ok(Recorder {
+            format,
+            config,
+            frame_skip: config.frame_skip.unwrap_or(format.default_frame_skip()).max(1),
+            out: Some(out),
+            size: None,
+            frames: 0,
+            written: 0,
+            pending: None,
+            error: None,
+        })
+    }
+
+    // frames_written returns how many frames are in the file so far
+    pub fn frames_written(&self) -> u64 {
+        self.written
+    }
+
+    // frame is called with the screen of every 60Hz frame
+    pub fn frame(&mut self, screen: &Screen) {
+        let frame = self.frames;
+        self.frames += 1;
+        if !frame.is_multiple_of(self.frame_skip as u64) || self.out.is_none() {
+            return;
+        }
+        let (width, height) = *self.size.get_or_insert((screen.width * self.config.scale.max(1),
+                                                         screen.height * self.config.scale.max(1)));
+        let image = Image::from_screen_sized(screen, &self.config.palette, width, height);
+        if let Err(err) = self.write(image, frame) {
+            self.out = None;
+            self.error = Some(err);
+        }
+    }
+
+    fn write(&mut self, image: Image, frame: u64) -> io::Result<()> {
+        let Some(out) = &mut self.out else {
+            return Ok(());
+        };
+        match self.format {
+            VideoFormat::Gif => {
+                // a GIF frame is written once the next one differs, so still frames become one long frame
+                match self.pending.take() {
+                    None => {
+                        out.write_all(&gif::header(&image))?;
+                        self.pending = Some((image, frame));
+                    },
+                    Some((pending, start)) if pending.pixels == image.pixels => self.pending = Some((pending, start)),
+                    Some((pending, start)) => {
+                        out.write_all(&gif::frame(&pending, gif_delay(start, frame)))?;
+                        self.written += 1;
+                        self.pending = Some((image, frame));
+                    },
+                }
+            },
+            VideoFormat::Y4m => {
+                if self.written == 0 {
+                    out.write_all(&y4m::header(&image, (60, self.frame_skip)))?;
+                }
+                out.write_all(&y4m::frame(&image))?;
+                self.written += 1;
+            },
+        }
+        Ok(())
+    }
+
+    // finish writes what is left and returns the first error that stopped the recording
+    pub fn finish(mut self) -> io::Result<()> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        let Some(mut out) = self.out.take() else {
+            return Ok(());
+        };
+        if let Some((pending, start)) = self.pending.take() {
+            let end = self.frames.max(start + self.frame_skip as u64);
+            out.write_all(&gif::frame(&pending, gif_delay(start, end)))?;
+            out.write_all(&[gif::TRAILER])?;
+        }
+        out.flush()
+    }
+}
+
+// gif_delay is the length in hundredths of a second of a GIF frame shown from one 60Hz frame
+// to another. It is rounded from the start of the recording, so the video doesn't drift
+fn gif_delay(start: u64, end: u64) -> u16 {
+    let time = |frame: u64| (frame * 100 + 30) / 60;
+    (time(end) - time(start)).min(u16::MAX as u64) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_gif_and_y4m() {
+        let lit = [1; 64 * 32];
+        let dark = [0; 64 * 32];
+        let screens = [&dark, &dark, &lit, &lit, &lit, &lit, &dark, &dark];
+
+        let path = std::env::temp_dir().join(format!("miko_chip8_{}_clip.gif", std::process::id()));
+        let mut recorder = Recorder::create(&path, VideoConfig { scale: 1, ..VideoConfig::default() }).unwrap();
+        for pixels in screens {
+            recorder.frame(&Screen { width: 64, height: 32, pixels });
+        }
+        recorder.finish().unwrap();
+        let data = std::fs::read(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(&data[..10], b"GIF89a\x40\x00\x20\x00");
+        assert_eq!(data.last(), Some(&gif::TRAILER));
+        // frame skip 2: dark for frames 0-1, lit for 2-5, dark for 6-7
+        let delays: Vec<u16> = data.windows(4).filter(|window| window[..3] == [0x21, 0xF9, 0x04])
+            .map(|window| window.as_ptr() as usize - data.as_ptr() as usize)
+            .map(|at| u16::from_le_bytes([data[at + 4], data[at + 5]]))
+            .collect();
+        assert_eq!(delays, [3, 7, 3]);
+
+        let path = path.with_extension("y4m");
+        let mut recorder = Recorder::create(&path, VideoConfig { scale: 2, ..VideoConfig::default() }).unwrap();
+        for pixels in screens {
+            recorder.frame(&Screen { width: 64, height: 32, pixels });
+        }
+        recorder.frame(&Screen { width: 128, height: 64, pixels: &[1; 128 * 64] });
+        assert_eq!(recorder.frames_written(), 9);
+        recorder.finish().unwrap();
+        let data = std::fs::read(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let header = b"YUV4MPEG2 W128 H64 F60:1 Ip A1:1 C444\n";
+        assert_eq!(&data[..header.len()], header);
+        assert_eq!(data.len(), header.len() + 9 * (6 + 3 * 128 * 64));
+    }
+}
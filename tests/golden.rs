@@ -0,0 +1,153 @@
+// golden screens: every case runs a ROM without a window for a number of frames, pressing
+// keys from a script, and compares the screen with tests/golden/<name>.txt.
+// BLESS=1 cargo test --test golden writes the goldens instead, check them with git diff.
+// the ROMs of tests/roms are assembled with our own assembler, they cover the opcodes,
+// the flags and the quirks like the community test suites do
+use std::fs;
+use std::path::{Path, PathBuf};
+use miko_chip8emulator::asm::assemble_file;
+use miko_chip8emulator::chip8::{Chip8, Quirks};
+use miko_chip8emulator::machine::Machine;
+
+// Case is one ROM run and the screen it is expected to end with
+struct Case {
+    rom: PathBuf, // a .ch8 file, or a .asm file that is assembled first
+    quirks: Quirks,
+    instructions_per_frame: u32,
+    frames: u64,
+    keys: Vec<(u64, u8, bool)>, // (frame, key, pressed), applied before the frame runs
+}
+
+impl Case {
+    fn example(name: &str) -> Case {
+        Case::new(Path::new("example_roms").join(name))
+    }
+
+    fn source(name: &str) -> Case {
+        Case::new(Path::new("tests/roms").join(name))
+    }
+
+    fn new(rom: PathBuf) -> Case {
+        Case {
+            rom: Path::new(env!("CARGO_MANIFEST_DIR")).join(rom),
+            quirks: Quirks::default(),
+            instructions_per_frame: 10,
+            frames: 60,
+            keys: Vec::new(),
+        }
+    }
+
+    fn quirks(self, quirks: Quirks) -> Case {
+        Case { quirks, ..self }
+    }
+
+    fn frames(self, frames: u64) -> Case {
+        Case { frames, ..self }
+    }
+
+    // tap holds key from frame for the given number of frames
+    fn tap(mut self, frame: u64, key: u8, frames: u64) -> Case {
+        self.keys.push((frame, key, true));
+        self.keys.push((frame + frames, key, false));
+        self
+    }
+
+    fn run(&self) -> String {
+        let rom = match self.rom.extension().and_then(|extension| extension.to_str()) {
+            Some("asm") => assemble_file(&self.rom).unwrap_or_else(|err| panic!("{}", err)),
+            _ => fs::read(&self.rom).unwrap_or_else(|err| panic!("cannot read {}: {}", self.rom.display(), err)),
+        };
+        let mut chip8 = Chip8::with_seed(self.quirks, 0);
+        chip8.load_rom(rom).unwrap();
+        let mut machine = Machine::new(chip8, self.instructions_per_frame);
+        for frame in 0..self.frames {
+            for &(_, key, pressed) in self.keys.iter().filter(|(at, _, _)| *at == frame) {
+                if pressed {
+                    machine.chip8_mut().key_down(key as usize);
+                } else {
+                    machine.chip8_mut().key_up(key as usize);
+                }
+            }
+            machine.run_frame().unwrap_or_else(|err| panic!("crashed in frame {}: {}", frame, err));
+        }
+        machine.framebuffer().to_string()
+    }
+
+    // check compares the screen with the golden, or replaces the golden in bless mode
+    fn check(&self, name: &str) {
+        let screen = self.run();
+        let golden = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(format!("{}.txt", name));
+        if std::env::var_os("BLESS").is_some() {
+            fs::write(&golden, &screen).unwrap();
+            return;
+        }
+        let expected = fs::read_to_string(&golden)
+            .unwrap_or_else(|err| panic!("cannot read {} ({}), run with BLESS=1 to create it", golden.display(), err));
+        if screen != expected {
+            let rows: Vec<String> = screen.lines().zip(expected.lines()).enumerate()
+                .filter(|(_, (got, want))| got != want)
+                .map(|(row, (got, want))| format!("row {:2}  got  {}\n         want {}", row, got, want))
+                .collect();
+            panic!("screen differs from {}:\n{}\n\nscreen:\n{}", golden.display(), rows.join("\n"), screen);
+        }
+    }
+}
+
+#[test]
+fn opcodes() {
+    Case::source("opcodes.asm").check("opcodes");
+}
+
+#[test]
+fn flags() {
+    Case::source("flags.asm").check("flags");
+}
+
+#[test]
+fn quirks_vip() {
+    Case::source("quirks.asm").quirks(Quirks::cosmac_vip()).check("quirks_vip");
+}
+
+#[test]
+fn quirks_chip48() {
+    Case::source("quirks.asm").quirks(Quirks::chip48()).check("quirks_chip48");
+}
+
+#[test]
+fn quirks_superchip() {
+    Case::source("quirks.asm").quirks(Quirks::superchip()).check("quirks_superchip");
+}
+
+#[test]
+fn quirks_modern() {
+    Case::source("quirks.asm").quirks(Quirks::modern()).check("quirks_modern");
+}
+
+#[test]
+fn keys() {
+    Case::source("keys.asm").tap(5, 0xA, 3).tap(15, 0x3, 1).tap(25, 0x0, 10).check("keys");
+}
+
+#[test]
+fn ibm_logo() {
+    Case::example("IBM Logo.ch8").check("ibm_logo");
+}
+
+#[test]
+fn keypad_test() {
+    Case::example("Keypad Test [Hap, 2006].ch8").quirks(Quirks::cosmac_vip()).tap(30, 0x5, 20).frames(60)
+        .check("keypad_test");
+}
+
+#[test]
+fn pong() {
+    Case::example("Pong (alt).ch8").quirks(Quirks::cosmac_vip()).frames(180).tap(20, 0x1, 30)
+        .check("pong");
+}
+
+#[test]
+fn tetris() {
+    Case::example("Tetris [Fran Dachille, 1991].ch8").quirks(Quirks::cosmac_vip()).frames(400)
+        .tap(60, 0x5, 10).tap(100, 0x6, 5).tap(140, 0x7, 30)
+        .check("tetris");
+}
@@ -0,0 +1,129 @@
+; flags.asm checks VF after the arithmetic instructions, including when VF is also
+; the result register: the flag is written last. Marks are drawn by report.asm
+
+    LD VD, 0
+    LD VE, 0
+
+; 8xy4 carry
+    LD V0, 0xFF
+    LD V1, 0x02
+    ADD V0, V1
+    LD V2, VF
+    SNE V0, 0x01
+    CALL ok
+    SE V0, 0x01
+    CALL fail
+    SNE V2, 1
+    CALL ok
+    SE V2, 1
+    CALL fail
+    LD V0, 0x10
+    ADD V0, V1
+    LD V2, VF
+    SNE V2, 0
+    CALL ok
+    SE V2, 0
+    CALL fail
+
+; 8xy5, VF is 1 when there is no borrow
+    LD V0, 0x10
+    LD V1, 0x20
+    SUB V0, V1
+    LD V2, VF
+    SNE V0, 0xF0
+    CALL ok
+    SE V0, 0xF0
+    CALL fail
+    SNE V2, 0
+    CALL ok
+    SE V2, 0
+    CALL fail
+    LD V0, 0x20
+    LD V1, 0x20
+    SUB V0, V1
+    LD V2, VF
+    SNE V2, 1
+    CALL ok
+    SE V2, 1
+    CALL fail
+
+; 8xy7
+    LD V0, 0x20
+    LD V1, 0x10
+    SUBN V0, V1
+    LD V2, VF
+    SNE V0, 0xF0
+    CALL ok
+    SE V0, 0xF0
+    CALL fail
+    SNE V2, 0
+    CALL ok
+    SE V2, 0
+    CALL fail
+
+; 8xy6 and 8xyE shift the lost bit into VF
+    LD V0, 0x81
+    SHR V0
+    LD V2, VF
+    SNE V2, 1
+    CALL ok
+    SE V2, 1
+    CALL fail
+    LD V0, 0x81
+    SHL V0
+    LD V2, VF
+    SNE V2, 1
+    CALL ok
+    SE V2, 1
+    CALL fail
+    LD V0, 0x40
+    SHL V0
+    LD V2, VF
+    SNE V2, 0
+    CALL ok
+    SE V2, 0
+    CALL fail
+
+; VF as the result register ends up holding the flag
+    LD VF, 0xFF
+    LD V1, 0x02
+    ADD VF, V1
+    LD V2, VF
+    SNE V2, 1
+    CALL ok
+    SE V2, 1
+    CALL fail
+    LD VF, 0x10
+    LD V1, 0x20
+    SUB VF, V1
+    LD V2, VF
+    SNE V2, 0
+    CALL ok
+    SE V2, 0
+    CALL fail
+    LD VF, 0x03
+    SHR VF
+    LD V2, VF
+    SNE V2, 1
+    CALL ok
+    SE V2, 1
+    CALL fail
+
+; VF as the other operand is read before the flag is written
+    LD V0, 0xFF
+    LD VF, 0x01
+    ADD V0, VF
+    LD V2, VF
+    SNE V0, 0x00
+    CALL ok
+    SE V0, 0x00
+    CALL fail
+    SNE V2, 1
+    CALL ok
+    SE V2, 1
+    CALL fail
+
+done:
+    JP done
+
+include "report.asm"
@@ -0,0 +1,14 @@
+; keys.asm waits for a key with Fx0A and draws its digit, then for the key to be
+; released and the next one pressed
+
+    LD VD, 0
+    LD VE, 0
+loop:
+    LD VC, K
+    CALL show
+held:
+    SKNP VC
+    JP held
+    JP loop
+
+include "report.asm"
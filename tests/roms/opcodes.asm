@@ -0,0 +1,200 @@
+; opcodes.asm checks the instructions that work the same with every quirk preset.
+; every check draws a tick if it passed and a cross if it failed, see report.asm
+
+    LD VD, 0
+    LD VE, 0
+
+; 6xkk and 3xkk, 4xkk
+    LD V0, 0x42
+    SNE V0, 0x42
+    CALL ok
+    SE V0, 0x42
+    CALL fail
+
+; 5xy0 and 9xy0
+    LD V1, 0x42
+    SNE V0, V1
+    CALL ok
+    SE V0, V1
+    CALL fail
+
+; 7xkk wraps around and leaves VF alone
+    LD VF, 5
+    LD V2, 0xF0
+    ADD V2, 0x20
+    LD V3, VF
+    SNE V2, 0x10
+    CALL ok
+    SE V2, 0x10
+    CALL fail
+    SNE V3, 5
+    CALL ok
+    SE V3, 5
+    CALL fail
+
+; 8xy1, 8xy2, 8xy3
+    LD V4, 0x3C
+    LD V5, 0x0F
+    OR V4, V5
+    SNE V4, 0x3F
+    CALL ok
+    SE V4, 0x3F
+    CALL fail
+    LD V4, 0x3C
+    AND V4, V5
+    SNE V4, 0x0C
+    CALL ok
+    SE V4, 0x0C
+    CALL fail
+    LD V4, 0x3C
+    XOR V4, V5
+    SNE V4, 0x33
+    CALL ok
+    SE V4, 0x33
+    CALL fail
+
+; 8xy4, 8xy5, 8xy7
+    LD V0, 0x10
+    LD V1, 0x30
+    ADD V0, V1
+    SNE V0, 0x40
+    CALL ok
+    SE V0, 0x40
+    CALL fail
+    SUB V0, V1
+    SNE V0, 0x10
+    CALL ok
+    SE V0, 0x10
+    CALL fail
+    SUBN V0, V1
+    SNE V0, 0x20
+    CALL ok
+    SE V0, 0x20
+    CALL fail
+
+; 8xy6 and 8xyE on the register itself
+    LD V0, 0x84
+    SHR V0
+    SNE V0, 0x42
+    CALL ok
+    SE V0, 0x42
+    CALL fail
+    SHL V0
+    SNE V0, 0x84
+    CALL ok
+    SE V0, 0x84
+    CALL fail
+
+; 1nnn
+    JP jumped
+    CALL fail
+jumped:
+    CALL ok
+
+; 2nnn and 00EE
+    LD V0, 0
+    CALL set_v0
+    SNE V0, 0x99
+    CALL ok
+    SE V0, 0x99
+    CALL fail
+
+; Annn, Fx1E and Fx65
+    LD I, data
+    LD V0, 2
+    ADD I, V0
+    LD V0, [I]
+    SNE V0, 0x22
+    CALL ok
+    SE V0, 0x22
+    CALL fail
+
+; Fx33
+    LD V0, 234
+    LD I, scratch
+    LD B, V0
+    LD V2, [I]
+    SNE V0, 2
+    CALL ok
+    SE V0, 2
+    CALL fail
+    SNE V1, 3
+    CALL ok
+    SE V1, 3
+    CALL fail
+    SNE V2, 4
+    CALL ok
+    SE V2, 4
+    CALL fail
+
+; Fx55 and Fx65
+    LD V0, 1
+    LD V1, 2
+    LD V2, 3
+    LD I, scratch
+    LD [I], V2
+    LD V0, 0
+    LD V1, 0
+    LD V2, 0
+    LD I, scratch
+    LD V2, [I]
+    SNE V1, 2
+    CALL ok
+    SE V1, 2
+    CALL fail
+
+; Fx29, the first row of A
+    LD V0, 0xA
+    LD F, V0
+    LD V0, [I]
+    SNE V0, 0xF0
+    CALL ok
+    SE V0, 0xF0
+    CALL fail
+
+; Dxyn sets VF when it erases a pixel
+    LD V0, 48
+    LD V1, 26
+    LD I, cross
+    DRW V0, V1, 5
+    LD V2, VF
+    DRW V0, V1, 5
+    LD V3, VF
+    SNE V2, 0
+    CALL ok
+    SE V2, 0
+    CALL fail
+    SNE V3, 1
+    CALL ok
+    SE V3, 1
+    CALL fail
+
+; Ex9E and ExA1 with no key held
+    LD V0, 5
+    SKNP V0
+    CALL fail
+    SKP V0
+    CALL ok
+
+; Fx15 and Fx07, the delay timer counts down to 0
+    LD V0, 3
+    LD DT, V0
+wait:
+    LD V1, DT
+    SE V1, 0
+    JP wait
+    CALL ok
+
+done:
+    JP done
+
+set_v0:
+    LD V0, 0x99
+    RET
+
+data:
+    byte 0x00, 0x11, 0x22, 0x33
+scratch:
+    byte 0, 0, 0, 0
+
+include "report.asm"
@@ -0,0 +1,70 @@
+; quirks.asm shows how the quirky instructions behave, one digit each:
+;   vf reset      1 if 8xy1 set VF to 0
+;   shift         1 if 8xy6 shifted Vy into Vx
+;   index         0 if Fx55 and Fx65 set I to I + x + 1, 1 for I + x, F if they left I alone
+;   jump          1 if Bnnn added Vx instead of V0
+; and a sprite drawn across the right edge, it wraps around to the left unless it is clipped
+
+    LD VD, 0
+    LD VE, 0
+
+; vf reset
+    LD VF, 5
+    LD V0, 1
+    LD V1, 2
+    OR V0, V1
+    LD VC, 0
+    SNE VF, 0
+    LD VC, 1
+    CALL show
+
+; shift
+    LD V0, 0x01
+    LD V1, 0x10
+    SHR V0, V1
+    LD VC, 0
+    SNE V0, 0x08
+    LD VC, 1
+    CALL show
+
+; index, the data is 1 byte per address so the byte read next tells where I went
+    LD I, data
+    LD V1, [I]
+    LD V0, [I]
+    LD VC, V0
+    CALL show
+
+; jump, with the quirk BXNN adds the register named by the high nibble of the address of
+; jumps, V2 or V3. Both are 2, so it lands on the second entry
+    LD V0, 0
+    LD V2, 2
+    LD V3, 2
+    JP V0, jumps
+jumps:
+    JP no_vx
+    JP vx
+no_vx:
+    LD VC, 0
+    JP jumped
+vx:
+    LD VC, 1
+jumped:
+    CALL show
+
+; clip
+    LD V0, 60
+    LD V1, 20
+    LD I, box
+    DRW V0, V1, 8
+
+done:
+    JP done
+
+; the byte the second Fx65 reads is the digit shown for the index quirk
+data:
+    byte 0xF, 1, 0
+
+box:
+    byte 0xFF, 0x81, 0x81, 0x81, 0x81, 0x81, 0x81, 0xFF
+
+include "report.asm"
@@ -0,0 +1,30 @@
+; report.asm draws results, included at the end of the test ROMs.
+; VD and VE are where the next mark goes, eight marks fit in a row.
+; checks use V0 to VC, the marks change VF and I
+
+; ok draws a tick, fail a cross
+ok:
+    LD I, tick
+    JP mark
+fail:
+    LD I, cross
+mark:
+    DRW VD, VE, 5
+next:
+    ADD VD, 8
+    SE VD, 64
+    RET
+    LD VD, 0
+    ADD VE, 6
+    RET
+
+; show draws the hexadecimal digit in VC
+show:
+    LD F, VC
+    DRW VD, VE, 5
+    JP next
+
+tick:
+    byte 0x04, 0x04, 0x88, 0x50, 0x20
+cross:
+    byte 0x88, 0x50, 0x20, 0x50, 0x88